@@ -1,7 +1,10 @@
+extern crate gl_generator;
+
 use std::env;
 use std::path::PathBuf;
 use std::fs; 
 
+mod egl;
 mod shader;
 
 fn main() {
@@ -11,6 +14,7 @@ fn main() {
     fs::create_dir(&out).unwrap();
 
     shader::build_shaders();
+    egl::build_egl_bindings();
 
 }
 
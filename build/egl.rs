@@ -0,0 +1,18 @@
+use std::env;
+use std::path::PathBuf;
+use std::fs::File;
+
+use gl_generator::{Api, Fallbacks, Profile, Registry, StructGenerator};
+
+
+//loaded at runtime, so windowed builds don't need libEGL
+pub fn build_egl_bindings() {
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let mut f = File::create(out.join("egl_bindings.rs")).unwrap();
+
+    Registry::new(Api::Egl, (1, 5), Profile::Core, Fallbacks::All, [
+        "EGL_EXT_platform_base",
+        "EGL_MESA_platform_surfaceless",
+        "EGL_KHR_surfaceless_context",
+    ]).write_bindings(StructGenerator, &mut f).unwrap();
+}
@@ -29,6 +29,7 @@ fn main() {
             0.5, -0.5,
             0.5, 0.5
         ],
+        ..Default::default()
     };
 
     let col_buf = Buffer {
@@ -39,6 +40,7 @@ fn main() {
             1.0, 0.0, 1.0,
             1.0, 1.0, 1.0,
         ],
+        ..Default::default()
     };
     
 
@@ -47,6 +49,7 @@ fn main() {
             0,1,2,
             0,2,3,
         ],
+        topology : indices::Topology::Triangles as i32,
     };
 
 
@@ -75,6 +78,8 @@ fn main() {
         program_namespace : "".to_string(),
         attribute_namespaces : vec!["tri".to_string()],
         uniform_namespaces : Vec::new(),
+        topology : indices::Topology::Triangles as i32,
+        instance_count : 0,
     };
     let mut recv_buffer : Vec<u8> = Vec::new();
     recv_buffer.resize(2048,0);
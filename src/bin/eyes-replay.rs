@@ -0,0 +1,163 @@
+extern crate eyes;
+extern crate glutin;
+extern crate clap;
+
+use clap::Arg;
+
+use std::io;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use eyes::renderer::{Renderer, describe};
+use eyes::config::Config;
+use eyes::surface::Surface;
+use eyes::capture::FrameCapture;
+use eyes::record::RecordReader;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    RealTime, //keep the recorded timing
+    Fast, //as fast as possible
+    Step, //one frame per key press
+}
+
+/// Blocks until the user asks for the next frame: space or enter in the
+/// window, or a line on stdin when headless. False if the window closed.
+fn wait_for_step(surface : &mut Surface) -> bool {
+    if surface.is_headless() {
+        let mut line = String::new();
+        return io::stdin().read_line(&mut line).map(|n| n > 0).unwrap_or(false);
+    }
+
+    let mut step = None;
+    while step.is_none() {
+        surface.poll_events(|event| {
+            match event {
+                glutin::Event::WindowEvent { event:glutin::WindowEvent::Closed, ..} => { step = Some(false); },
+                glutin::Event::WindowEvent { event:glutin::WindowEvent::KeyboardInput { input, .. }, ..} => {
+                    let pressed = input.state == glutin::ElementState::Pressed;
+                    match input.virtual_keycode {
+                        Some(glutin::VirtualKeyCode::Space) | Some(glutin::VirtualKeyCode::Return) if pressed => { step = Some(true); },
+                        _ => (),
+                    }
+                },
+                _ => ()
+            }
+        });
+        thread::sleep(Duration::from_millis(10));
+    }
+    step.unwrap()
+}
+
+fn fail(msg : String) -> ! {
+    println!("{}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let matches = Config::app("eyes-replay")
+        .arg(Arg::with_name("file")
+            .required(true)
+            .help("Command log written by eyes --record"))
+        .arg(Arg::with_name("mode")
+            .long("mode")
+            .takes_value(true)
+            .possible_values(&["realtime", "fast", "step"])
+            .default_value("realtime"))
+        .get_matches();
+
+    let config = Config::from_matches(&matches).unwrap_or_else(|e| fail(e));
+    let path = matches.value_of("file").unwrap();
+    let mode = match matches.value_of("mode") {
+        Some("fast") => Mode::Fast,
+        Some("step") => Mode::Step,
+        _ => Mode::RealTime,
+    };
+
+    let mut records = RecordReader::open(path, config.max_message_size)
+        .unwrap_or_else(|e| fail(format!("can't open {}: {}", path, e)));
+
+    let mut surface = Surface::new(&config).unwrap_or_else(|e| fail(e));
+    let mut renderer = Renderer::new(Vec::new());
+    let capture = FrameCapture::new(&config);
+
+    let start = Instant::now();
+    let mut first_timestamp : Option<u64> = None;
+
+    let mut running = true;
+    let mut finished = false;
+
+    while running && !finished {
+        renderer.clear();
+
+        loop {
+            surface.poll_events(|event|{
+                match event {
+                    glutin::Event::WindowEvent { event:glutin::WindowEvent::Closed, ..} => { running = false; },
+                    _ => ()
+                }
+            });
+
+            if !running {
+                break;
+            }
+
+            let record = match records.next() {
+                Some(Ok(record)) => record,
+                Some(Err(e)) => {
+                    println!("bad record: {}", e);
+                    finished = true;
+                    break;
+                },
+                None => {
+                    finished = true;
+                    break;
+                },
+            };
+
+            if mode == Mode::RealTime {
+                let first = *first_timestamp.get_or_insert(record.timestamp_ns);
+                let offset = record.timestamp_ns.saturating_sub(first);
+                let due = start + Duration::new(offset / 1_000_000_000, (offset % 1_000_000_000) as u32);
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+            }
+
+            let cmd = match record.command {
+                Some(cmd) => cmd,
+                None => continue,
+            };
+            if config.verbose {
+                println!("{} {}", String::from_utf8_lossy(&record.prefix[..]), describe(&cmd));
+            }
+            //screenshots have nobody to go to
+            if let Some(frame) = renderer.apply(&record.prefix[..], cmd) {
+                capture.frame_done(frame.frame_number, &frame.captures[..], surface.size());
+                break;
+            }
+        }
+
+        surface.present();
+
+        if running && !finished && mode == Mode::Step {
+            running = wait_for_step(&mut surface);
+        }
+    }
+
+    //keep showing the last frame until the window is closed
+    if finished && !surface.is_headless() {
+        println!("end of {}", path);
+        while running {
+            surface.poll_events(|event|{
+                match event {
+                    glutin::Event::WindowEvent { event:glutin::WindowEvent::Closed, ..} => { running = false; },
+                    _ => ()
+                }
+            });
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
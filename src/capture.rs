@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+
+use png;
+use png::HasParameters;
+
+use config::Config;
+use gl_util::read_pixels;
+
+/// Writes finished frames to PNG files, for a range of frame numbers and
+/// whenever a Capture command asks for one.
+pub struct FrameCapture {
+    dir : PathBuf,
+    frames : Option<(u32, u32)>, //inclusive
+}
+
+pub fn encode_png<W : Write>(w : W, width : u32, height : u32, rgba : &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    Ok(())
+}
+
+pub fn write_png(path : &PathBuf, width : u32, height : u32, rgba : &[u8]) -> io::Result<()> {
+    let file = File::create(path)?;
+    encode_png(BufWriter::new(file), width, height, rgba)
+}
+
+/// True for a plain file name. Capture paths come from the network, so
+/// anything that could leave the capture directory is refused.
+fn is_bare_file_name(name : &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(|c : char| c == '/' || c == '\\' || c == ':' || c == '\0')
+}
+
+impl FrameCapture {
+    pub fn new(config : &Config) -> FrameCapture {
+        FrameCapture {
+            dir : PathBuf::from(&config.capture_dir),
+            frames : config.capture_frames,
+        }
+    }
+
+    fn frame_path(&self, frame_number : u32) -> PathBuf {
+        self.dir.join(format!("frame_{:06}.png", frame_number))
+    }
+
+    /// Call when `frame_number` is done, before it is presented. `requested`
+    /// are file names from Capture commands, written to the capture
+    /// directory. An empty one picks a numbered file.
+    pub fn frame_done(&self, frame_number : u32, requested : &[String], size : (u32, u32)) {
+        let mut paths : Vec<PathBuf> = Vec::new();
+        if let Some((first, last)) = self.frames {
+            if first <= frame_number && frame_number <= last {
+                paths.push(self.frame_path(frame_number));
+            }
+        }
+        for name in requested {
+            if name.is_empty() {
+                paths.push(self.frame_path(frame_number));
+            } else if is_bare_file_name(name) {
+                paths.push(self.dir.join(name));
+            } else {
+                println!("refusing capture to {:?}, only file names are allowed", name);
+            }
+        }
+
+        paths.dedup();
+        if paths.is_empty() {
+            return;
+        }
+
+        let (width, height) = size;
+        if width == 0 || height == 0 {
+            println!("skipping capture of frame {}, the framebuffer is {}x{}", frame_number, width, height);
+            return;
+        }
+        let pixels = read_pixels(width, height);
+        for path in paths {
+            match write_png(&path, width, height, &pixels[..]) {
+                Ok(()) => println!("captured frame {} to {:?}", frame_number, path),
+                Err(e) => println!("capture to {:?} failed: {}", path, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_bare_file_name;
+
+    #[test]
+    fn file_names_are_allowed() {
+        assert!(is_bare_file_name("frame.png"));
+        assert!(is_bare_file_name("..frame.png"));
+    }
+
+    #[test]
+    fn paths_are_refused() {
+        assert!(!is_bare_file_name(""));
+        assert!(!is_bare_file_name("."));
+        assert!(!is_bare_file_name(".."));
+        assert!(!is_bare_file_name("/home/u/.bashrc"));
+        assert!(!is_bare_file_name("../../x"));
+        assert!(!is_bare_file_name("sub/frame.png"));
+        assert!(!is_bare_file_name("..\\x"));
+        assert!(!is_bare_file_name("C:x"));
+    }
+}
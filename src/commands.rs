@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::rc::Rc;
+
+use gl;
+
+use mzmr_proto::cmd::*;
+use mzmr_proto::cmd::set_resource::*;
+
+use gl_util::*;
+use gl_util::Texture;
+use gl_util::InterleavedBuffer;
+
+include!(concat!(env!("OUT_DIR"), "/default.shader"));
+
+fn build_and_use_program(state : &Rc<GlState>) -> ShaderProgram {
+	let prog = ShaderProgram::build_program(state, default::shader).unwrap();
+    prog.bind();
+    prog
+}
+
+//`what` names the attribute in error messages
+fn attribute_format(what : &str, dim : u32, columns : u32, divisor : u32, component_type : i32, normalized : bool, integer : bool) -> Option<AttributeFormat> {
+    let component_type = match buffer::ComponentType::from_i32(component_type) {
+        Some(buffer::ComponentType::Float) => ComponentType::F32,
+        Some(buffer::ComponentType::HalfFloat) => ComponentType::F16,
+        Some(buffer::ComponentType::Uint8) => ComponentType::U8,
+        Some(buffer::ComponentType::Int8) => ComponentType::I8,
+        Some(buffer::ComponentType::Uint16) => ComponentType::U16,
+        Some(buffer::ComponentType::Int16) => ComponentType::I16,
+        Some(buffer::ComponentType::Uint32) => ComponentType::U32,
+        Some(buffer::ComponentType::Int32) => ComponentType::I32,
+        None => {
+            println!("unknown component type {} for {}", component_type, what);
+            return None;
+        }
+    };
+    let kind = match (normalized, integer) {
+        (false, false) => AttributeKind::Float,
+        (true, false) => AttributeKind::Normalized,
+        (false, true) => AttributeKind::Integer,
+        (true, true) => {
+            println!("{} can't be both normalized and integer", what);
+            return None;
+        }
+    };
+    let format = AttributeFormat {
+        dim : dim as usize,
+        //matrices and arrays, unset for plain vectors
+        columns : if columns == 0 { 1 } else { columns as usize },
+        divisor : divisor,
+        component_type : component_type,
+        kind : kind,
+    };
+    if !format.is_valid() {
+        println!("invalid format for {}: {:?}", what, format);
+        return None;
+    }
+    Some(format)
+}
+
+fn buffer_format(buffer : &Buffer, name : &String, namespace : &String) -> Option<AttributeFormat> {
+    let what = format!("buffer {}/{}", namespace, name);
+    attribute_format(&what, buffer.dim, buffer.columns, buffer.divisor, buffer.component_type, buffer.normalized, buffer.integer)
+}
+
+//f32 data can only go to F32 buffers, everything else is sent as bytes
+fn check_data(format : AttributeFormat, name : &String, namespace : &String, data : &[f32], bytes : &[u8]) -> bool {
+    if bytes.is_empty() && !data.is_empty() && format.component_type != ComponentType::F32 {
+        println!("buffer {}/{} has {:?} components, send them as bytes", namespace, name, format.component_type);
+        return false;
+    }
+    true
+}
+
+fn set_buffer(state : &Rc<GlState>, table : &mut HashMap<String, HashMap<String,AttributeBuffer>>, name : &String, namespace : &String, buffer : Buffer) {
+    let format = match buffer_format(&buffer, name, namespace) {
+        Some(format) => format,
+        None => return,
+    };
+    //before touching the table, so a rejected upload keeps the old buffer
+    if !check_data(format, name, namespace, &buffer.data[..], &buffer.bytes[..]) {
+        return;
+    }
+    let buffers = table.entry(namespace.clone()).or_insert_with(|| HashMap::new());
+    //a new layout needs a new buffer
+    let replace = buffers.get(name).map(|buf| buf.get_format() != format).unwrap_or(true);
+    if replace {
+        buffers.insert(name.clone(), AttributeBuffer::new(state, format));
+    }
+    let buf = buffers.get_mut(name).unwrap();
+    if buffer.bytes.is_empty() {
+        buf.fill(&buffer.data[..]);
+    } else {
+        buf.fill_bytes(&buffer.bytes[..]);
+    }
+}
+
+fn update_buffer(table : &mut HashMap<String, HashMap<String,AttributeBuffer>>, name : &String, namespace : &String, offset : Option<u32>, data : &[f32], bytes : &[u8]) {
+    let buf = match table.get_mut(namespace).and_then(|buffers| buffers.get_mut(name)) {
+        Some(buf) => buf,
+        None => {
+            println!("no buffer {}/{} to update", namespace, name);
+            return;
+        }
+    };
+    if !check_data(buf.get_format(), name, namespace, data, bytes) {
+        return;
+    }
+    match (offset, bytes.is_empty()) {
+        (Some(offset), true) => buf.update(offset as usize, data),
+        (Some(offset), false) => buf.update_bytes(offset as usize, bytes),
+        (None, true) => buf.append(data),
+        (None, false) => buf.append_bytes(bytes),
+    }
+}
+
+fn set_interleaved(state : &Rc<GlState>, table : &mut HashMap<String, HashMap<String,InterleavedBuffer>>, name : &String, namespace : &String, interleaved : ::mzmr_proto::cmd::InterleavedBuffer) {
+    let mut layout = Vec::with_capacity(interleaved.attributes.len());
+    for attribute in interleaved.attributes {
+        let what = format!("attribute {} of {}/{}", attribute.name, namespace, name);
+        let format = match attribute_format(&what, attribute.dim, attribute.columns, attribute.divisor, attribute.component_type, attribute.normalized, attribute.integer) {
+            Some(format) => format,
+            None => return,
+        };
+        layout.push(InterleavedAttribute {
+            name : attribute.name,
+            format : format,
+            offset : attribute.offset as usize,
+            stride : attribute.stride as usize,
+        });
+    }
+    let buf = table.entry(namespace.clone()).or_insert_with(|| HashMap::new()).entry(name.clone()).or_insert_with(|| InterleavedBuffer::new(state));
+    buf.fill(layout, &interleaved.data[..]);
+}
+
+fn topology_to_gl(topology : i32) -> Option<gl::types::GLenum> {
+    match indices::Topology::from_i32(topology) {
+        Some(indices::Topology::Triangles) => Some(gl::TRIANGLES),
+        Some(indices::Topology::Points) => Some(gl::POINTS),
+        Some(indices::Topology::Lines) => Some(gl::LINES),
+        Some(indices::Topology::LineStrip) => Some(gl::LINE_STRIP),
+        Some(indices::Topology::LineLoop) => Some(gl::LINE_LOOP),
+        Some(indices::Topology::TriangleStrip) => Some(gl::TRIANGLE_STRIP),
+        Some(indices::Topology::TriangleFan) => Some(gl::TRIANGLE_FAN),
+        None => None,
+    }
+}
+
+fn set_indices(state : &Rc<GlState>, table : &mut HashMap<String, HashMap<String,IndexBuffer>>, name:&String, namespace:&String, topology : i32, data : &[u32]) {
+    let topology = match topology_to_gl(topology) {
+        Some(topology) => topology,
+        None => {
+            println!("unknown topology {} for {}/{}", topology, namespace, name);
+            return;
+        }
+    };
+    let buf = table.entry(namespace.clone()).or_insert_with(|| HashMap::new()).entry(name.clone()).or_insert_with(|| IndexBuffer::new(state));
+    buf.fill(topology, data);
+}
+
+//int_data wins when both are set
+fn constant_value(data : Vec<f32>, int_data : Vec<i32>) -> ConstantAttribute {
+    if int_data.is_empty() {
+        ConstantAttribute::Float(data)
+    } else {
+        ConstantAttribute::Int(int_data)
+    }
+}
+
+fn set_program(state : &Rc<GlState>, table : &mut HashMap<String, HashMap<String,ShaderProgram>>, name : &String, namespace : &String, shader : Shader) {
+    let Shader { vertex_source : vertex, fragment_source : fragment, defaults } = shader;
+    let (vertex, fragment) = match (CString::new(vertex), CString::new(fragment)) {
+        (Ok(vertex), Ok(fragment)) => (vertex, fragment),
+        _ => {
+            println!("shader {}/{} contains a nul byte", namespace, name);
+            return;
+        }
+    };
+    //a broken shader keeps the last working program with this name
+    let mut prog = match ShaderProgram::build_program(state, (vertex.as_bytes_with_nul(), fragment.as_bytes_with_nul())) {
+        Ok(prog) => prog,
+        Err(e) => {
+            println!("shader {}/{}: {}", namespace, name, e);
+            return;
+        }
+    };
+    for shader::AttributeDefault { name, data, int_data } in defaults {
+        prog.defaults.insert(name, constant_value(data, int_data));
+    }
+    table.entry(namespace.clone()).or_insert_with(|| HashMap::new()).insert(name.clone(), prog);
+}
+
+fn set_uniform(table : &mut HashMap<String, HashMap<String,UniformValue>>, name : &String, namespace : &String, uniform : Uniform) {
+    let Uniform { kind, data, int_data } = uniform;
+    let value = match uniform::Kind::from_i32(kind) {
+        Some(uniform::Kind::Float) => UniformValue::Float(1, data),
+        Some(uniform::Kind::Vec2) => UniformValue::Float(2, data),
+        Some(uniform::Kind::Vec3) => UniformValue::Float(3, data),
+        Some(uniform::Kind::Vec4) => UniformValue::Float(4, data),
+        Some(uniform::Kind::Mat2) => UniformValue::Matrix(2, data),
+        Some(uniform::Kind::Mat3) => UniformValue::Matrix(3, data),
+        Some(uniform::Kind::Mat4) => UniformValue::Matrix(4, data),
+        Some(uniform::Kind::Int) => UniformValue::Int(1, int_data),
+        Some(uniform::Kind::Ivec2) => UniformValue::Int(2, int_data),
+        Some(uniform::Kind::Ivec3) => UniformValue::Int(3, int_data),
+        Some(uniform::Kind::Ivec4) => UniformValue::Int(4, int_data),
+        None => {
+            println!("unknown uniform kind {} for {}/{}", kind, namespace, name);
+            return;
+        }
+    };
+    table.entry(namespace.clone()).or_insert_with(|| HashMap::new()).insert(name.clone(), value);
+}
+
+fn set_texture(table : &mut HashMap<String, HashMap<String,Texture>>, name : &String, namespace : &String, width : u32, height : u32, format : i32, data : &[u8]) {
+    let format = match texture::Format::from_i32(format) {
+        Some(texture::Format::R8) => TextureFormat::R8,
+        Some(texture::Format::Rg8) => TextureFormat::Rg8,
+        Some(texture::Format::Rgb8) => TextureFormat::Rgb8,
+        Some(texture::Format::Rgba8) => TextureFormat::Rgba8,
+        Some(texture::Format::R32f) => TextureFormat::R32F,
+        Some(texture::Format::Rgba32f) => TextureFormat::Rgba32F,
+        None => {
+            println!("unknown texture format {} for {}/{}", format, namespace, name);
+            return;
+        }
+    };
+    let tex = table.entry(namespace.clone()).or_insert_with(|| HashMap::new()).entry(name.clone()).or_insert_with(|| Texture::new());
+    tex.fill(width, height, format, data);
+}
+
+fn get_program<'a>(table : &'a HashMap<String, HashMap<String,ShaderProgram>>, default_prog : &'a ShaderProgram, name : &String, namespace : &String) -> Option<&'a ShaderProgram> {
+    if name.is_empty() {
+        return Some(default_prog);
+    }
+    table.get(namespace).and_then(|programs| programs.get(name))
+}
+
+/// Removes `name` from `namespace`, or the whole namespace when `name` is
+/// empty. False if there was nothing to remove.
+fn delete_from<T>(table : &mut HashMap<String, HashMap<String,T>>, name : &String, namespace : &String) -> bool {
+    if name.is_empty() {
+        return table.remove(namespace).is_some();
+    }
+
+    let (found, now_empty) = match table.get_mut(namespace) {
+        Some(resources) => (resources.remove(name).is_some(), resources.is_empty()),
+        None => (false, false),
+    };
+    if now_empty {
+        table.remove(namespace);
+    }
+    found
+}
+
+/// Everything publishers have sent: buffers, constant attributes, programs,
+/// uniforms and textures, by namespace and name.
+pub struct Resources {
+    state : Rc<GlState>,
+    default_prog : ShaderProgram,
+    attribute_buffers : HashMap<String, HashMap<String,AttributeBuffer>>,
+    interleaved_buffers : HashMap<String, HashMap<String,InterleavedBuffer>>,
+    index_buffers : HashMap<String, HashMap<String,IndexBuffer>>,
+    constants : HashMap<String, HashMap<String,ConstantAttribute>>,
+    programs : HashMap<String, HashMap<String,ShaderProgram>>,
+    uniforms : HashMap<String, HashMap<String,UniformValue>>,
+    textures : HashMap<String, HashMap<String,Texture>>,
+}
+
+impl Resources {
+    /// Needs a current GL context. Every context needs its own.
+    pub fn new() -> Resources {
+        let state = GlState::new();
+        Resources {
+            default_prog : build_and_use_program(&state),
+            state : state,
+            attribute_buffers : HashMap::new(),
+            interleaved_buffers : HashMap::new(),
+            index_buffers : HashMap::new(),
+            constants : HashMap::new(),
+            programs : HashMap::new(),
+            uniforms : HashMap::new(),
+            textures : HashMap::new(),
+        }
+    }
+
+    pub fn set_resource(&mut self, set_resource : SetResource) {
+        let SetResource { name, namespace, resource, .. } = set_resource;
+        match resource {
+            None => (),
+            Some(Resource::Buffer(buffer)) => {
+                set_buffer(&self.state, &mut self.attribute_buffers, &name, &namespace, buffer);
+            },
+            Some(Resource::BufferUpdate(BufferUpdate { offset, data, bytes })) => {
+                update_buffer(&mut self.attribute_buffers, &name, &namespace, Some(offset), &data[..], &bytes[..]);
+            },
+            Some(Resource::BufferAppend(BufferAppend { data, bytes })) => {
+                update_buffer(&mut self.attribute_buffers, &name, &namespace, None, &data[..], &bytes[..]);
+            },
+            Some(Resource::InterleavedBuffer(interleaved)) => {
+                set_interleaved(&self.state, &mut self.interleaved_buffers, &name, &namespace, interleaved);
+            },
+            Some(Resource::Indices(Indices { data, topology })) => {
+                set_indices(&self.state, &mut self.index_buffers, &name, &namespace, topology, &data[..]);
+            },
+            Some(Resource::Shader(shader)) => {
+                set_program(&self.state, &mut self.programs, &name, &namespace, shader);
+            },
+            Some(Resource::Constant(Constant { data, int_data })) => {
+                self.constants.entry(namespace.clone()).or_insert_with(|| HashMap::new()).insert(name.clone(), constant_value(data, int_data));
+            },
+            Some(Resource::Uniform(uniform)) => {
+                set_uniform(&mut self.uniforms, &name, &namespace, uniform);
+            },
+            Some(Resource::Texture(texture)) => {
+                set_texture(&mut self.textures, &name, &namespace, texture.width, texture.height, texture.format, &texture.data[..]);
+            },
+            _ => (),
+        }
+    }
+
+    /// Deletes every resource called `name` in `namespace`, or all of
+    /// `namespace` when `name` is empty.
+    pub fn delete(&mut self, name : &String, namespace : &String) {
+        //not short circuiting, names can be shared across resource types
+        let found = delete_from(&mut self.attribute_buffers, name, namespace)
+            | delete_from(&mut self.interleaved_buffers, name, namespace)
+            | delete_from(&mut self.index_buffers, name, namespace)
+            | delete_from(&mut self.constants, name, namespace)
+            | delete_from(&mut self.programs, name, namespace)
+            | delete_from(&mut self.uniforms, name, namespace)
+            | delete_from(&mut self.textures, name, namespace);
+        if !found {
+            println!("nothing to delete at {}/{}", namespace, name);
+        }
+    }
+
+    /// For when other code has changed the context's bindings.
+    pub fn invalidate(&self) {
+        self.state.invalidate();
+    }
+
+    /// Deletes every resource. The default program stays.
+    pub fn clear(&mut self) {
+        self.attribute_buffers.clear();
+        self.interleaved_buffers.clear();
+        self.index_buffers.clear();
+        self.constants.clear();
+        self.programs.clear();
+        self.uniforms.clear();
+        self.textures.clear();
+    }
+
+    pub fn draw(&self, draw : Draw) {
+        let Draw { program_name, program_namespace, attribute_namespaces, uniform_namespaces, topology, instance_count, .. } = draw;
+        let prog = match get_program(&self.programs, &self.default_prog, &program_name, &program_namespace) {
+            Some(prog) => prog,
+            None => {
+                println!("no program {}/{}", program_namespace, program_name);
+                return;
+            }
+        };
+
+        let mut draw_call = DrawCall::new(&self.state);
+
+        match topology_to_gl(topology) {
+            Some(topology) => draw_call.set_topology(topology),
+            None => println!("unknown draw topology {}", topology),
+        }
+        //0 for a plain draw
+        if instance_count > 0 {
+            draw_call.set_instance_count(instance_count as usize);
+        }
+
+        for namespace in &attribute_namespaces {
+            if let Some(some_namespace) = self.index_buffers.get(namespace) {
+                if let Some(index_buffer) = some_namespace.get("indices") {
+                    draw_call.set_indices(index_buffer);
+                    break;
+                }
+            }
+        }
+
+        draw_call.set_program(prog);
+        //buffer, then constant, then the program's default
+        for input in &prog.inputs {
+            let name = &input.name;
+            let mut found = false;
+            for namespace in &attribute_namespaces {
+                if let Some(some_namespace) = self.attribute_buffers.get(namespace) {
+                    if let Some(attrs) = some_namespace.get(name) {
+                        match draw_call.add_attrs(name.as_str(), attrs) {
+                            Ok(()) => found = true,
+                            Err(e) => println!("skipping attribute {}: {}", name, e),
+                        }
+                        break;
+                    }
+                }
+                //a separate buffer wins over an interleaved one in the same namespace
+                //the first buffer by name, so hash order can't pick a different one each run
+                let interleaved = self.interleaved_buffers.get(namespace).and_then(|buffers| {
+                    buffers.iter()
+                        .filter_map(|(buf_name, buf)| buf.get_attribute(name).map(|attr| (buf_name, buf, attr)))
+                        .min_by_key(|&(buf_name, _, _)| buf_name)
+                        .map(|(_, buf, attr)| (buf, attr))
+                });
+                if let Some((buf, attr)) = interleaved {
+                    match draw_call.add_interleaved_attr(buf, attr) {
+                        Ok(()) => found = true,
+                        Err(e) => println!("skipping attribute {}: {}", name, e),
+                    }
+                    break;
+                }
+            }
+            if found {
+                continue;
+            }
+
+            let constant = attribute_namespaces.iter()
+                .filter_map(|namespace| self.constants.get(namespace).and_then(|constants| constants.get(name)))
+                .next()
+                .or_else(|| prog.defaults.get(name));
+            if let Some(value) = constant {
+                draw_call.add_constant(input, value);
+            }
+        }
+
+        for uniform in &prog.uniforms {
+            if uniform.data_type == gl::SAMPLER_2D {
+                for namespace in &uniform_namespaces {
+                    if let Some(some_namespace) = self.textures.get(namespace) {
+                        if let Some(texture) = some_namespace.get(&uniform.name) {
+                            draw_call.add_texture(uniform, texture);
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            for namespace in &uniform_namespaces {
+                if let Some(some_namespace) = self.uniforms.get(namespace) {
+                    if let Some(value) = some_namespace.get(&uniform.name) {
+                        draw_call.add_uniform(uniform, value);
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = draw_call.draw() {
+            println!("draw failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use gl;
+    use mzmr_proto::cmd::indices;
+
+    use super::{delete_from, topology_to_gl};
+
+    fn table() -> HashMap<String, HashMap<String,u32>> {
+        let mut table = HashMap::new();
+        let mut tri = HashMap::new();
+        tri.insert("position".to_string(), 1);
+        tri.insert("color".to_string(), 2);
+        table.insert("tri".to_string(), tri);
+        let mut quad = HashMap::new();
+        quad.insert("position".to_string(), 3);
+        table.insert("quad".to_string(), quad);
+        table
+    }
+
+    #[test]
+    fn delete_one_resource() {
+        let mut table = table();
+        assert!(delete_from(&mut table, &"position".to_string(), &"tri".to_string()));
+        assert_eq!(None, table["tri"].get("position"));
+        assert_eq!(Some(&2), table["tri"].get("color"));
+        assert_eq!(Some(&3), table["quad"].get("position"));
+    }
+
+    #[test]
+    fn delete_last_resource_drops_namespace() {
+        let mut table = table();
+        assert!(delete_from(&mut table, &"position".to_string(), &"quad".to_string()));
+        assert!(!table.contains_key("quad"));
+    }
+
+    #[test]
+    fn delete_whole_namespace() {
+        let mut table = table();
+        assert!(delete_from(&mut table, &String::new(), &"tri".to_string()));
+        assert!(!table.contains_key("tri"));
+        assert!(table.contains_key("quad"));
+    }
+
+    #[test]
+    fn delete_missing() {
+        let mut table = table();
+        assert!(!delete_from(&mut table, &"normal".to_string(), &"tri".to_string()));
+        assert!(!delete_from(&mut table, &"position".to_string(), &"cube".to_string()));
+        assert!(!delete_from(&mut table, &String::new(), &"cube".to_string()));
+        assert_eq!(2, table["tri"].len());
+    }
+
+    #[test]
+    fn topologies() {
+        assert_eq!(Some(gl::TRIANGLES), topology_to_gl(indices::Topology::Triangles as i32));
+        assert_eq!(Some(gl::POINTS), topology_to_gl(indices::Topology::Points as i32));
+        assert_eq!(Some(gl::LINE_LOOP), topology_to_gl(indices::Topology::LineLoop as i32));
+        assert_eq!(Some(gl::TRIANGLE_FAN), topology_to_gl(indices::Topology::TriangleFan as i32));
+        assert_eq!(None, topology_to_gl(-1));
+    }
+}
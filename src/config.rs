@@ -0,0 +1,221 @@
+use std::fs::File;
+use std::io::Read;
+
+use clap::{App, Arg, ArgMatches};
+use rand::Rng;
+use toml;
+
+/// Viewer settings. Loaded from an optional TOML file, then overridden by
+/// command line options.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub connect : Vec<String>,
+    pub subscribe : Vec<String>,
+    pub reply : Vec<String>,
+    pub name : Option<String>,
+    pub width : u32,
+    pub height : u32,
+    pub title : String,
+    pub vsync : bool,
+    pub headless : bool,
+    pub record : Option<String>,
+    pub capture_dir : String,
+    pub capture_frames : Option<(u32, u32)>, //first and last frame number
+    pub max_message_size : usize, //bytes
+    pub verbose : bool, //log every command
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            connect : vec!["tcp://127.0.0.1:1234".to_string()],
+            subscribe : vec!["render/".to_string()],
+            reply : Vec::new(),
+            name : None,
+            width : 1024,
+            height : 1024,
+            title : "eyes".to_string(),
+            vsync : true,
+            headless : false,
+            record : None,
+            capture_dir : ".".to_string(),
+            capture_frames : None,
+            max_message_size : 64 << 20,
+            verbose : false,
+        }
+    }
+}
+
+fn parse_arg<T : ::std::str::FromStr>(matches : &ArgMatches, name : &str) -> Result<Option<T>, String> {
+    match matches.value_of(name) {
+        Some(value) => value.parse().map(Some).map_err(|_| format!("invalid value for --{}: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+fn parse_range(value : &str) -> Option<(u32, u32)> {
+    let mut parts = value.splitn(2, ':');
+    match (parts.next().map(str::parse), parts.next().map(str::parse)) {
+        (Some(Ok(first)), Some(Ok(last))) => Some((first, last)),
+        (Some(Ok(frame)), None) => Some((frame, frame)),
+        _ => None,
+    }
+}
+
+fn values(matches : &ArgMatches, name : &str) -> Option<Vec<String>> {
+    matches.values_of(name).map(|values| values.map(|v| v.to_string()).collect())
+}
+
+impl Config {
+    pub fn load(path : &str) -> Result<Config, String> {
+        let mut text = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+            return Err(format!("can't read config {}: {}", path, e));
+        }
+        toml::from_str(&text).map_err(|e| format!("invalid config {}: {}", path, e))
+    }
+
+    /// Options shared by every front end: config file, display, capture,
+    /// message size and logging.
+    pub fn app<'a, 'b>(name : &str) -> App<'a, 'b> {
+        App::new(name)
+            .arg(Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .help("TOML file with default settings"))
+            .arg(Arg::with_name("width")
+                .long("width")
+                .takes_value(true))
+            .arg(Arg::with_name("height")
+                .long("height")
+                .takes_value(true))
+            .arg(Arg::with_name("title")
+                .long("title")
+                .takes_value(true))
+            .arg(Arg::with_name("no-vsync")
+                .long("no-vsync"))
+            .arg(Arg::with_name("headless")
+                .long("headless")
+                .help("Render offscreen at --width x --height without opening a window (needs libEGL)"))
+            .arg(Arg::with_name("capture-dir")
+                .long("capture-dir")
+                .takes_value(true)
+                .help("Directory for captured frames"))
+            .arg(Arg::with_name("capture-frames")
+                .long("capture-frames")
+                .takes_value(true)
+                .help("Frame numbers to capture as PNG, FIRST:LAST"))
+            .arg(Arg::with_name("max-message-size")
+                .long("max-message-size")
+                .takes_value(true)
+                .help("Largest command accepted, in bytes"))
+            .arg(Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .help("Print a line for every command"))
+    }
+
+    /// Exits through clap on bad command line syntax.
+    pub fn from_args() -> Result<Config, String> {
+        let matches = Config::app("eyes")
+            .arg(Arg::with_name("connect")
+                .long("connect")
+                .short("c")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Publisher endpoint to connect to, may be repeated"))
+            .arg(Arg::with_name("subscribe")
+                .long("subscribe")
+                .short("s")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Topic prefix to render, may be repeated"))
+            .arg(Arg::with_name("reply")
+                .long("reply")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Endpoint to publish replies (screenshots, input events) to, may be repeated"))
+            .arg(Arg::with_name("name")
+                .long("name")
+                .takes_value(true)
+                .help("Instance name, addressed as @<name> (random by default)"))
+            .arg(Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("File to record every received command to"))
+            .get_matches();
+
+        Config::from_matches(&matches)
+    }
+
+    /// Options missing from `matches` keep their config file or default value.
+    pub fn from_matches(matches : &ArgMatches) -> Result<Config, String> {
+        let mut config = match matches.value_of("config") {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(connect) = values(matches, "connect") {
+            config.connect = connect;
+        }
+        if let Some(subscribe) = values(matches, "subscribe") {
+            config.subscribe = subscribe;
+        }
+        if let Some(reply) = values(matches, "reply") {
+            config.reply = reply;
+        }
+        if let Some(name) = matches.value_of("name") {
+            config.name = Some(name.to_string());
+        }
+        if let Some(width) = parse_arg(matches, "width")? {
+            config.width = width;
+        }
+        if let Some(height) = parse_arg(matches, "height")? {
+            config.height = height;
+        }
+        if let Some(title) = matches.value_of("title") {
+            config.title = title.to_string();
+        }
+        if matches.is_present("no-vsync") {
+            config.vsync = false;
+        }
+        if matches.is_present("headless") {
+            config.headless = true;
+        }
+        if let Some(record) = matches.value_of("record") {
+            config.record = Some(record.to_string());
+        }
+        if let Some(capture_dir) = matches.value_of("capture-dir") {
+            config.capture_dir = capture_dir.to_string();
+        }
+        if let Some(range) = matches.value_of("capture-frames") {
+            match parse_range(range) {
+                Some(frames) => config.capture_frames = Some(frames),
+                None => return Err(format!("invalid value for --capture-frames: {}", range)),
+            }
+        }
+        if let Some(max_message_size) = parse_arg(matches, "max-message-size")? {
+            config.max_message_size = max_message_size;
+        }
+        if matches.is_present("verbose") {
+            config.verbose = true;
+        }
+
+        Ok(config)
+    }
+
+    /// The direct subscription topic for this viewer.
+    pub fn instance_name(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("@{}", name),
+            None => {
+                let instance_id : u64 = ::rand::thread_rng().gen();
+                format!("@{:016X}", instance_id)
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::ffi::{CStr, CString};
+use std::os::raw;
+use std::ptr;
+
+use libc;
+use libloading::{Library, Symbol};
+
+#[allow(non_camel_case_types, non_snake_case, non_upper_case_globals, dead_code)]
+mod ffi {
+    use libc;
+
+    pub mod egl {
+        pub type khronos_utime_nanoseconds_t = super::khronos_utime_nanoseconds_t;
+        pub type khronos_uint64_t = super::khronos_uint64_t;
+        pub type khronos_ssize_t = super::khronos_ssize_t;
+        pub type EGLNativeDisplayType = super::EGLNativeDisplayType;
+        pub type EGLNativePixmapType = super::EGLNativePixmapType;
+        pub type EGLNativeWindowType = super::EGLNativeWindowType;
+        pub type EGLint = super::EGLint;
+        pub type NativeDisplayType = super::EGLNativeDisplayType;
+        pub type NativePixmapType = super::EGLNativePixmapType;
+        pub type NativeWindowType = super::EGLNativeWindowType;
+
+        include!(concat!(env!("OUT_DIR"), "/egl_bindings.rs"));
+    }
+
+    pub type khronos_utime_nanoseconds_t = khronos_uint64_t;
+    pub type khronos_uint64_t = libc::uint64_t;
+    pub type khronos_ssize_t = libc::c_long;
+    pub type EGLint = libc::int32_t;
+    pub type EGLNativeDisplayType = *const libc::c_void;
+    pub type EGLNativePixmapType = *const libc::c_void;
+    pub type EGLNativeWindowType = *const libc::c_void;
+}
+
+use self::ffi::egl;
+use self::ffi::egl::types::*;
+
+//from EGL_MESA_platform_surfaceless
+const PLATFORM_SURFACELESS_MESA : EGLenum = 0x31DD;
+
+unsafe fn query_string(egl : &egl::Egl, display : EGLDisplay, name : EGLint) -> String {
+    let s = egl.QueryString(display, name);
+    if s.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+fn has_extension(extensions : &str, name : &str) -> bool {
+    extensions.split(' ').any(|extension| extension == name)
+}
+
+/// An OpenGL 3.3 core context without a window, from EGL.
+///
+/// Uses a surfaceless display and context (EGL_MESA_platform_surfaceless,
+/// EGL_KHR_surfaceless_context) when the driver has them, a pbuffer
+/// otherwise, so it works with Mesa's llvmpipe on hosts without a display.
+/// libEGL is loaded when the context is created.
+pub struct EglContext {
+    egl : egl::Egl,
+    display : EGLDisplay,
+    context : EGLContext, //null until created
+    surface : EGLSurface, //null when surfaceless
+    _lib : Library, //keeps the entry points in `egl` loaded
+}
+
+impl Drop for EglContext {
+    fn drop(&mut self) {
+        unsafe {
+            self.egl.MakeCurrent(self.display, ptr::null(), ptr::null(), ptr::null());
+            if !self.surface.is_null() {
+                self.egl.DestroySurface(self.display, self.surface);
+            }
+            if !self.context.is_null() {
+                self.egl.DestroyContext(self.display, self.context);
+            }
+            self.egl.Terminate(self.display);
+        }
+    }
+}
+
+impl EglContext {
+    /// Creates the context and makes it current. `width` and `height` size
+    /// the pbuffer when there is one.
+    pub fn new(width : u32, height : u32) -> Result<EglContext, String> {
+        let lib = Library::new("libEGL.so.1")
+            .or_else(|_| Library::new("libEGL.so"))
+            .map_err(|e| format!("can't load libEGL: {}", e))?;
+
+        let egl = unsafe {
+            let get_proc_address : Symbol<unsafe extern "C" fn(*const raw::c_char) -> *const raw::c_void> =
+                lib.get(b"eglGetProcAddress\0").map_err(|e| format!("can't load libEGL: {}", e))?;
+            //extension entry points are only found through eglGetProcAddress
+            egl::Egl::load_with(|name| {
+                let symbol = CString::new(name).unwrap();
+                match lib.get::<*const raw::c_void>(symbol.as_bytes_with_nul()) {
+                    Ok(f) => *f,
+                    Err(_) => get_proc_address(symbol.as_ptr()),
+                }
+            })
+        };
+
+        unsafe {
+            let client_extensions = query_string(&egl, ptr::null(), egl::EXTENSIONS as EGLint);
+            let display = if has_extension(&client_extensions, "EGL_MESA_platform_surfaceless") && egl.GetPlatformDisplayEXT.is_loaded() {
+                egl.GetPlatformDisplayEXT(PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null())
+            } else {
+                egl.GetDisplay(ptr::null())
+            };
+            if display.is_null() {
+                return Err("no EGL display".to_string());
+            }
+
+            let mut major = 0;
+            let mut minor = 0;
+            if egl.Initialize(display, &mut major, &mut minor) == 0 {
+                return Err(format!("can't initialize EGL, error {:#x}", egl.GetError()));
+            }
+
+            //from here on Drop cleans up
+            let mut context = EglContext {
+                egl : egl,
+                display : display,
+                context : ptr::null(),
+                surface : ptr::null(),
+                _lib : lib,
+            };
+            context.create(width, height)?;
+            println!("EGL {}.{}", major, minor);
+            Ok(context)
+        }
+    }
+
+    unsafe fn create(&mut self, width : u32, height : u32) -> Result<(), String> {
+        let egl = &self.egl;
+        let extensions = query_string(egl, self.display, egl::EXTENSIONS as EGLint);
+        let surfaceless = has_extension(&extensions, "EGL_KHR_surfaceless_context");
+
+        if egl.BindAPI(egl::OPENGL_API) == 0 {
+            return Err(format!("EGL has no desktop OpenGL, error {:#x}", egl.GetError()));
+        }
+
+        let surface_type = if surfaceless { 0 } else { egl::PBUFFER_BIT as EGLint };
+        let config_attribs = [
+            egl::SURFACE_TYPE as EGLint, surface_type,
+            egl::RENDERABLE_TYPE as EGLint, egl::OPENGL_BIT as EGLint,
+            egl::RED_SIZE as EGLint, 8,
+            egl::GREEN_SIZE as EGLint, 8,
+            egl::BLUE_SIZE as EGLint, 8,
+            egl::ALPHA_SIZE as EGLint, 8,
+            egl::NONE as EGLint,
+        ];
+        let mut config : EGLConfig = ptr::null();
+        let mut config_count = 0;
+        if egl.ChooseConfig(self.display, config_attribs.as_ptr(), &mut config, 1, &mut config_count) == 0 || config_count == 0 {
+            return Err("no EGL config for an RGBA8 OpenGL context".to_string());
+        }
+
+        let context_attribs = [
+            egl::CONTEXT_MAJOR_VERSION as EGLint, 3,
+            egl::CONTEXT_MINOR_VERSION as EGLint, 3,
+            egl::CONTEXT_OPENGL_PROFILE_MASK as EGLint, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT as EGLint,
+            egl::NONE as EGLint,
+        ];
+        self.context = egl.CreateContext(self.display, config, ptr::null(), context_attribs.as_ptr());
+        if self.context.is_null() {
+            return Err(format!("can't create an OpenGL 3.3 core context, error {:#x}", egl.GetError()));
+        }
+
+        if !surfaceless {
+            let pbuffer_attribs = [
+                egl::WIDTH as EGLint, width as EGLint,
+                egl::HEIGHT as EGLint, height as EGLint,
+                egl::NONE as EGLint,
+            ];
+            self.surface = egl.CreatePbufferSurface(self.display, config, pbuffer_attribs.as_ptr());
+            if self.surface.is_null() {
+                return Err(format!("can't create a pbuffer, error {:#x}", egl.GetError()));
+            }
+        }
+
+        if egl.MakeCurrent(self.display, self.surface, self.surface, self.context) == 0 {
+            return Err(format!("can't make the EGL context current, error {:#x}", egl.GetError()));
+        }
+        Ok(())
+    }
+
+    pub fn get_proc_address(&self, name : &str) -> *const libc::c_void {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => return ptr::null(),
+        };
+        unsafe {
+            self.egl.GetProcAddress(name.as_ptr()) as *const _
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_extension;
+
+    #[test]
+    fn extensions_match_whole_names() {
+        let extensions = "EGL_KHR_surfaceless_context_x EGL_EXT_platform_base";
+        assert!(has_extension(extensions, "EGL_EXT_platform_base"));
+        assert!(!has_extension(extensions, "EGL_KHR_surfaceless_context"));
+    }
+}
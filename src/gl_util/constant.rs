@@ -0,0 +1,64 @@
+use gl;
+use gl::types::*;
+
+use super::shader::ShaderAttributeInfo;
+
+//data[start..end] over `fill`, as far as data goes
+fn column_value<T : Copy>(data : &[T], start : usize, end : usize, mut fill : [T; 4]) -> [T; 4] {
+    for i in start..end.min(data.len()) {
+        fill[i - start] = data[i];
+    }
+    fill
+}
+
+/// A value for a shader input that has no buffer, used by every vertex.
+/// Values are given per location (matrix column or array element), missing
+/// components are filled from (0, 0, 0, 1).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantAttribute {
+    Float(Vec<f32>),
+    Int(Vec<i32>),
+}
+
+impl ConstantAttribute {
+    /// Sets the current value of every location of `input`, which is read
+    /// while its array is disabled. Converted to the input's type, since GL
+    /// leaves the value undefined when the types differ.
+    pub fn upload(&self, input : &ShaderAttributeInfo) {
+        let unsigned = match input.data_type {
+            gl::UNSIGNED_INT | gl::UNSIGNED_INT_VEC2 | gl::UNSIGNED_INT_VEC3 | gl::UNSIGNED_INT_VEC4 => true,
+            _ => false,
+        };
+
+        for column in 0..input.location_count() {
+            let location = input.location + column as GLuint;
+            let start = column * input.dim;
+            let end = start + input.dim;
+
+            unsafe {
+                if unsigned {
+                    let data : Vec<u32> = match *self {
+                        ConstantAttribute::Float(ref data) => data.iter().map(|&v| v as u32).collect(),
+                        ConstantAttribute::Int(ref data) => data.iter().map(|&v| v as u32).collect(),
+                    };
+                    let v = column_value(&data, start, end, [0, 0, 0, 1]);
+                    gl::VertexAttribI4ui(location, v[0], v[1], v[2], v[3]);
+                } else if input.integer {
+                    let data : Vec<i32> = match *self {
+                        ConstantAttribute::Float(ref data) => data.iter().map(|&v| v as i32).collect(),
+                        ConstantAttribute::Int(ref data) => data.clone(),
+                    };
+                    let v = column_value(&data, start, end, [0, 0, 0, 1]);
+                    gl::VertexAttribI4i(location, v[0], v[1], v[2], v[3]);
+                } else {
+                    let data : Vec<f32> = match *self {
+                        ConstantAttribute::Float(ref data) => data.clone(),
+                        ConstantAttribute::Int(ref data) => data.iter().map(|&v| v as f32).collect(),
+                    };
+                    let v = column_value(&data, start, end, [0.0, 0.0, 0.0, 1.0]);
+                    gl::VertexAttrib4f(location, v[0], v[1], v[2], v[3]);
+                }
+            }
+        }
+    }
+}
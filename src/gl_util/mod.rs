@@ -1,8 +1,12 @@
+use std::error::Error;
+use std::fmt;
 use std::mem;
 use std::ptr;
+use std::slice;
 use std::str;
 use std::ops::Drop;
 use std::os::raw;
+use std::rc::Rc;
 
 
 use gl;
@@ -11,9 +15,15 @@ use gl::types::*;
 
 use std::ffi::CStr;
 
+mod constant;
 mod shader;
+mod state;
+mod uniform;
 
+pub use self::constant::*;
 pub use self::shader::*;
+pub use self::state::*;
+pub use self::uniform::*;
 
 
 pub fn init_gl<F>(f:F) where F:FnMut(&str) -> *const raw::c_void {
@@ -25,46 +35,190 @@ pub fn init_gl<F>(f:F) where F:FnMut(&str) -> *const raw::c_void {
         };
 
         println!("OpenGL version {}", version);
+    }
+}
+
+/// Reads RGBA pixels from the current framebuffer, top row first. Empty
+/// when either size is 0.
+pub fn read_pixels(width : u32, height : u32) -> Vec<u8> {
+    let row = (width as usize) * 4;
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let mut pixels = vec![0u8; row * (height as usize)];
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as GLsizei, height as GLsizei,
+            gl::RGBA, gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut _
+        );
+    }
+
+    //GL rows start at the bottom
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for line in pixels.chunks(row).rev() {
+        flipped.extend_from_slice(line);
+    }
+    flipped
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentType {
+    F32,
+    F16,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+}
+
+impl ComponentType {
+    pub fn gl_type(&self) -> GLenum {
+        match *self {
+            ComponentType::F32 => gl::FLOAT,
+            ComponentType::F16 => gl::HALF_FLOAT,
+            ComponentType::U8 => gl::UNSIGNED_BYTE,
+            ComponentType::I8 => gl::BYTE,
+            ComponentType::U16 => gl::UNSIGNED_SHORT,
+            ComponentType::I16 => gl::SHORT,
+            ComponentType::U32 => gl::UNSIGNED_INT,
+            ComponentType::I32 => gl::INT,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match *self {
+            ComponentType::F32 | ComponentType::U32 | ComponentType::I32 => 4,
+            ComponentType::F16 | ComponentType::U16 | ComponentType::I16 => 2,
+            ComponentType::U8 | ComponentType::I8 => 1,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        *self == ComponentType::F32 || *self == ComponentType::F16
+    }
+}
+
+/// How a shader sees the components of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeKind {
+    Float, //converted as is
+    Normalized, //integers mapped to [0,1] or [-1,1]
+    Integer, //for int/ivecN/uint/uvecN inputs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttributeFormat {
+    pub dim : usize, //components per location, 1-4
+    pub columns : usize, //consecutive locations, for matrix columns and array elements
+    pub divisor : u32, //0 advances per vertex, n advances every n instances
+    pub component_type : ComponentType,
+    pub kind : AttributeKind,
+}
+
+impl AttributeFormat {
+    pub fn float(dim : usize) -> AttributeFormat {
+        AttributeFormat {
+            dim : dim,
+            columns : 1,
+            divisor : 0,
+            component_type : ComponentType::F32,
+            kind : AttributeKind::Float,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let integer_ok = !(self.kind == AttributeKind::Integer && self.component_type.is_float());
+        let normalized_ok = !(self.kind == AttributeKind::Normalized && self.component_type.is_float());
+        self.dim >= 1 && self.dim <= 4 && self.columns >= 1 && integer_ok && normalized_ok
+    }
 
-        //bind a global vertex array object
-        let mut vao = mem::uninitialized();
-        gl::GenVertexArrays(1, &mut vao);
-        gl::BindVertexArray(vao);
+    pub fn vertex_size(&self) -> usize {
+        self.dim * self.columns * self.component_type.size()
+    }
+
+    /// Points attributes `location`.. `location + columns` at the bound
+    /// array buffer, one column each. A `stride` of 0 means packed.
+    pub unsafe fn attrib_pointer(&self, location : GLuint, stride : usize, offset : usize) {
+        let dim = self.dim as GLint;
+        let component_type = self.component_type.gl_type();
+        let stride = if stride == 0 { self.vertex_size() } else { stride } as GLsizei;
+        let column_size = self.dim * self.component_type.size();
+        for column in 0..self.columns {
+            let column_location = location + column as GLuint;
+            let column_offset = (offset + column * column_size) as *const raw::c_void;
+            match self.kind {
+                AttributeKind::Float => gl::VertexAttribPointer(column_location, dim, component_type, gl::FALSE, stride, column_offset),
+                AttributeKind::Normalized => gl::VertexAttribPointer(column_location, dim, component_type, gl::TRUE, stride, column_offset),
+                AttributeKind::Integer => gl::VertexAttribIPointer(column_location, dim, component_type, stride, column_offset),
+            }
+            gl::VertexAttribDivisor(column_location, self.divisor);
+            gl::EnableVertexAttribArray(column_location);
+        }
+    }
+}
+
+fn as_bytes<T>(data : &[T]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>())
     }
 }
 
 #[derive(Debug)]
 pub struct AttributeBuffer {
+	state : Rc<GlState>,
 	buffer : GLuint,
-	size : GLint, //1,2,3,4
+	format : AttributeFormat,
 	count : usize,
+	capacity : usize, //vertices the buffer has room for
 }
 
 impl Drop for AttributeBuffer {
 	fn drop(&mut self) {
+		self.state.forget_buffer(self.buffer);
 		unsafe {
 			gl::DeleteBuffers(1, &self.buffer);
 		}
 	}
 }
 
-unsafe fn fill_buffer<T>(buffer : GLuint, data : &[T]) {
+unsafe fn fill_buffer(buffer : GLuint, data : &[u8]) {
     gl::BindBuffer(gl::COPY_WRITE_BUFFER, buffer);
-    let byte_count = data.len() * mem::size_of::<T>();
     gl::BufferData(gl::COPY_WRITE_BUFFER,
-        byte_count as gl::types::GLsizeiptr,
+        data.len() as gl::types::GLsizeiptr,
         data.as_ptr() as *const _,
         gl::STATIC_DRAW
     );
 }
 
-#[allow(non_upper_case_globals)]
-static mut bound_attrs : [Option<GLuint> ; 16] = [None ; 16];
+unsafe fn update_buffer(buffer : GLuint, byte_offset : usize, data : &[u8]) {
+    gl::BindBuffer(gl::COPY_WRITE_BUFFER, buffer);
+    gl::BufferSubData(gl::COPY_WRITE_BUFFER,
+        byte_offset as GLintptr,
+        data.len() as GLsizeiptr,
+        data.as_ptr() as *const _
+    );
+}
+
+//returns a new buffer of `byte_count` bytes starting with the first
+//`keep_bytes` of `buffer`
+unsafe fn grow_buffer(buffer : GLuint, keep_bytes : usize, byte_count : usize) -> GLuint {
+    let mut grown = mem::uninitialized();
+    gl::GenBuffers(1, &mut grown);
+    gl::BindBuffer(gl::COPY_WRITE_BUFFER, grown);
+    gl::BufferData(gl::COPY_WRITE_BUFFER, byte_count as GLsizeiptr, ptr::null(), gl::DYNAMIC_DRAW);
+
+    gl::BindBuffer(gl::COPY_READ_BUFFER, buffer);
+    gl::CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, keep_bytes as GLsizeiptr);
+    grown
+}
 
 impl AttributeBuffer {
-	pub fn new(dim : u32) -> AttributeBuffer {
-		if dim < 1 || dim > 4 {
-			panic!();
+	pub fn new(state : &Rc<GlState>, format : AttributeFormat) -> AttributeBuffer {
+		if !format.is_valid() {
+			panic!("invalid attribute format {:?}", format);
 		}
 
     	let mut buf = unsafe { 
@@ -75,52 +229,166 @@ impl AttributeBuffer {
     	};
     	
     	AttributeBuffer {
+    		state : state.clone(),
     		buffer : buf,
-    		size : dim as GLint,
+    		format : format,
     		count : 0,
+    		capacity : 0,
     	}
 	}
 
     pub fn get_dim(&self) -> usize {
-        self.size as usize
+        self.format.dim
+    }
+
+    pub fn get_format(&self) -> AttributeFormat {
+        self.format
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
     }
 
+	/// For F32 buffers.
 	pub fn fill(&mut self, data : &[f32]) {
-		self.count = data.len()/(self.size as usize);
+		self.fill_bytes(as_bytes(data));
+	}
+
+	/// `data` holds packed vertices in the buffer's format.
+	pub fn fill_bytes(&mut self, data : &[u8]) {
+		self.count = data.len()/self.format.vertex_size();
+		self.capacity = self.count;
+		self.state.buffer_changed(self.buffer);
 		unsafe {
 			fill_buffer(self.buffer, data);
     	}
 	}
 
-	pub fn bind_to_current_vao(&self, attr : GLint) {
+	/// For F32 buffers, see `update_bytes`.
+	pub fn update(&mut self, offset : usize, data : &[f32]) {
+		self.update_bytes(offset, as_bytes(data));
+	}
+
+	/// Overwrites vertices from `offset` on, growing the buffer if `data`
+	/// runs past the end. `offset` can be at most the current count.
+	pub fn update_bytes(&mut self, offset : usize, data : &[u8]) {
+		if offset > self.count {
+			println!("buffer update at {} is past the end ({})", offset, self.count);
+			return;
+		}
+
+		let vertex_bytes = self.format.vertex_size();
+		let end = offset + data.len() / vertex_bytes;
+		if end > self.capacity {
+			//leave room for more appends
+			let capacity = end.max(self.capacity * 2);
+			unsafe {
+				let grown = grow_buffer(self.buffer, self.count * vertex_bytes, capacity * vertex_bytes);
+				self.state.forget_buffer(self.buffer);
+				gl::DeleteBuffers(1, &self.buffer);
+				self.buffer = grown;
+			}
+			self.capacity = capacity;
+		}
+
 		unsafe {
-            if Some(self.buffer) == bound_attrs[attr as usize] {
-                return;
-            }
-            clear_attr(attr as usize);
-            bound_attrs[attr as usize] = Some(self.buffer);
-    		gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
-    		gl::VertexAttribPointer(attr as gl::types::GLuint, self.size, gl::FLOAT, 0,
-        		0,
-        		ptr::null()
-    		);
-    		gl::EnableVertexAttribArray(attr as GLuint);
+			update_buffer(self.buffer, offset * vertex_bytes, &data[..(end - offset) * vertex_bytes]);
 		}
+		self.count = self.count.max(end);
+	}
+
+	/// For F32 buffers, see `append_bytes`.
+	pub fn append(&mut self, data : &[f32]) {
+		self.append_bytes(as_bytes(data));
+	}
+
+	/// Adds vertices after the current ones.
+	pub fn append_bytes(&mut self, data : &[u8]) {
+		let count = self.count;
+		self.update_bytes(count, data);
 	}
 }
 
-unsafe fn clear_attr(i : usize) {
-    if let Some(_) = bound_attrs[i] {
-        bound_attrs[i] = None;
-        gl::DisableVertexAttribArray(i as GLuint);
-    }
+/// One attribute of an `InterleavedBuffer`.
+#[derive(Debug, Clone)]
+pub struct InterleavedAttribute {
+    pub name : String,
+    pub format : AttributeFormat,
+    pub offset : usize, //bytes to the first vertex
+    pub stride : usize, //bytes between vertices, 0 for the whole layout's size
+}
+
+/// Several attributes sharing one GL buffer.
+#[derive(Debug)]
+pub struct InterleavedBuffer {
+	state : Rc<GlState>,
+	buffer : GLuint,
+	size : usize, //bytes
+	layout : Vec<InterleavedAttribute>,
 }
 
-#[allow(non_upper_case_globals)]
-static mut current_indices : GLuint = 0;
+impl Drop for InterleavedBuffer {
+	fn drop(&mut self) {
+		self.state.forget_buffer(self.buffer);
+		unsafe {
+			gl::DeleteBuffers(1, &self.buffer);
+		}
+	}
+}
+
+impl InterleavedBuffer {
+	pub fn new(state : &Rc<GlState>) -> InterleavedBuffer {
+    	let mut buf = unsafe { 
+    		mem::uninitialized() 
+    	};
+    	unsafe {
+    		gl::GenBuffers(1, &mut buf);
+    	};
+
+    	InterleavedBuffer {
+    		state : state.clone(),
+    		buffer : buf,
+    		size : 0,
+    		layout : Vec::new(),
+    	}
+	}
+
+	pub fn fill(&mut self, mut layout : Vec<InterleavedAttribute>, data : &[u8]) {
+		//stride 0 means every attribute sits in one packed vertex
+		let vertex_size = layout.iter().map(|attr| attr.offset + attr.format.vertex_size()).max().unwrap_or(0);
+		for attr in &mut layout {
+			if attr.stride == 0 {
+				attr.stride = vertex_size;
+			}
+		}
+
+		self.size = data.len();
+		self.layout = layout;
+		//the layout lives in the VAOs too
+		self.state.buffer_changed(self.buffer);
+		unsafe {
+			fill_buffer(self.buffer, data);
+		}
+	}
+
+	pub fn get_attribute(&self, name : &str) -> Option<&InterleavedAttribute> {
+		self.layout.iter().find(|attr| attr.name == name)
+	}
+
+	/// Vertices of `attr` that fit in the buffer.
+	pub fn get_count(&self, attr : &InterleavedAttribute) -> usize {
+		let end = attr.offset + attr.format.vertex_size();
+		if self.size < end {
+			0
+		} else {
+			(self.size - end) / attr.stride + 1
+		}
+	}
+}
 
 #[derive(Debug)]
 pub struct IndexBuffer {
+	state : Rc<GlState>,
 	buffer : GLuint,
 	count : usize,
 	topology : GLenum,
@@ -128,6 +396,7 @@ pub struct IndexBuffer {
 
 impl Drop for IndexBuffer {
 	fn drop(&mut self) {
+		self.state.forget_buffer(self.buffer);
 		unsafe {
 			gl::DeleteBuffers(1, &self.buffer);
 		}
@@ -135,7 +404,7 @@ impl Drop for IndexBuffer {
 }
 
 impl IndexBuffer {
-	pub fn new() -> IndexBuffer {
+	pub fn new(state : &Rc<GlState>) -> IndexBuffer {
     	let mut buf = unsafe { 
     		mem::uninitialized() 
     	};
@@ -144,6 +413,7 @@ impl IndexBuffer {
     	};
     	
     	IndexBuffer {
+    		state : state.clone(),
     		buffer : buf,
     		count : 0,
     		topology : gl::TRIANGLES,
@@ -153,43 +423,258 @@ impl IndexBuffer {
 	pub fn fill(&mut self, topology : GLenum, data : &[u32]) {
 		self.count = data.len();
 		self.topology = topology;
+		self.state.buffer_changed(self.buffer);
 		unsafe {
-    		fill_buffer(self.buffer, data);
+    		fill_buffer(self.buffer, as_bytes(data));
     	}
 	}
 
-	pub fn draw(&self) {
+	pub fn bind_to_current_vao(&self) {
 		unsafe {
+			gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.buffer);
+		}
+	}
 
-			if current_indices != self.buffer {
-				current_indices = self.buffer;
-				gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.buffer);
+	/// `instances` of None draws once without instancing.
+	pub fn draw(&self, instances : Option<usize>) {
+		unsafe {
+			match instances {
+				None => gl::DrawElements(
+            		self.topology,
+            		self.count as GLint,
+            		gl::UNSIGNED_INT,
+           			ptr::null()
+        		),
+				Some(instances) => gl::DrawElementsInstanced(
+            		self.topology,
+            		self.count as GLint,
+            		gl::UNSIGNED_INT,
+           			ptr::null(),
+           			instances as GLsizei
+        		),
 			}
-
-			gl::DrawElements(
-            	self.topology,
-            	self.count as GLint,
-            	gl::UNSIGNED_INT,
-           		ptr::null()
-        	);
 		}
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    R32F,
+    Rgba32F,
+}
+
+impl TextureFormat {
+    //(internal format, format, type, bytes per pixel)
+    fn gl_format(&self) -> (GLint, GLenum, GLenum, usize) {
+        match *self {
+            TextureFormat::R8 => (gl::R8 as GLint, gl::RED, gl::UNSIGNED_BYTE, 1),
+            TextureFormat::Rg8 => (gl::RG8 as GLint, gl::RG, gl::UNSIGNED_BYTE, 2),
+            TextureFormat::Rgb8 => (gl::RGB8 as GLint, gl::RGB, gl::UNSIGNED_BYTE, 3),
+            TextureFormat::Rgba8 => (gl::RGBA8 as GLint, gl::RGBA, gl::UNSIGNED_BYTE, 4),
+            TextureFormat::R32F => (gl::R32F as GLint, gl::RED, gl::FLOAT, 4),
+            TextureFormat::Rgba32F => (gl::RGBA32F as GLint, gl::RGBA, gl::FLOAT, 16),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Texture {
+    texture : GLuint,
+    width : u32,
+    height : u32,
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+impl Texture {
+    pub fn new() -> Texture {
+        let mut tex = unsafe {
+            mem::uninitialized()
+        };
+        unsafe {
+            gl::GenTextures(1, &mut tex);
+            gl::BindTexture(gl::TEXTURE_2D, tex);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        };
+
+        Texture {
+            texture : tex,
+            width : 0,
+            height : 0,
+        }
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Rows are tightly packed, starting from the bottom of the image.
+    pub fn fill(&mut self, width : u32, height : u32, format : TextureFormat, data : &[u8]) {
+        let (internal_format, pixel_format, pixel_type, pixel_size) = format.gl_format();
+        if data.len() < (width as usize) * (height as usize) * pixel_size {
+            println!("texture data too short for {}x{} {:?}", width, height, format);
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format,
+                width as GLsizei, height as GLsizei, 0,
+                pixel_format, pixel_type,
+                data.as_ptr() as *const _
+            );
+        }
+    }
+
+    pub fn bind(&self, unit : u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+        }
+    }
+}
+
+/// An offscreen color target.
+#[derive(Debug)]
+pub struct Framebuffer {
+    framebuffer : GLuint,
+    color : GLuint,
+    width : u32,
+    height : u32,
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(1, &self.color);
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn new(width : u32, height : u32) -> Framebuffer {
+        unsafe {
+            let mut framebuffer = mem::uninitialized();
+            let mut color = mem::uninitialized();
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::GenRenderbuffers(1, &mut color);
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, color);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as GLsizei, height as GLsizei);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, color);
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                println!("framebuffer incomplete: {:X}", status);
+            }
+
+            Framebuffer {
+                framebuffer : framebuffer,
+                color : color,
+                width : width,
+                height : height,
+            }
+        }
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DrawError {
+    NoProgram,
+    TooManyAttributes(usize), //the limit
+    NoSuchAttribute(String),
+    IntegerMismatch(String), //integer input without an integer buffer, or the other way around
+    ColumnMismatch(String), //buffer and input span a different number of locations
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DrawError::NoProgram => write!(f, "no program set"),
+            DrawError::TooManyAttributes(max) => write!(f, "more than {} attributes", max),
+            DrawError::NoSuchAttribute(ref name) => write!(f, "program has no attribute {}", name),
+            DrawError::IntegerMismatch(ref name) => write!(f, "attribute {} and its buffer disagree on integer components", name),
+            DrawError::ColumnMismatch(ref name) => write!(f, "attribute {} and its buffer span a different number of locations", name),
+        }
+    }
+}
+
+impl Error for DrawError {
+    fn description(&self) -> &str {
+        match *self {
+            DrawError::NoProgram => "no program set",
+            DrawError::TooManyAttributes(_) => "too many attributes",
+            DrawError::NoSuchAttribute(_) => "no such attribute",
+            DrawError::IntegerMismatch(_) => "integer attribute mismatch",
+            DrawError::ColumnMismatch(_) => "attribute column mismatch",
+        }
+    }
+}
+
+//where an attribute's data comes from
+#[derive(Debug, Clone, Copy)]
+struct AttributeSource<'a> {
+    name : &'a str,
+    buffer : GLuint,
+    format : AttributeFormat,
+    offset : usize,
+    stride : usize,
+    count : usize,
+}
+
 pub struct DrawCall<'a> {
+    state : &'a GlState,
     program : Option<&'a ShaderProgram>,
     indices : Option<&'a IndexBuffer>,
-    attrib_count : usize,
-    attribs : [Option<(&'a str, &'a AttributeBuffer)> ; 16],
+    topology : GLenum, //used when there are no indices
+    instance_count : Option<usize>,
+    attribs : Vec<AttributeSource<'a>>,
+    constants : Vec<(&'a ShaderAttributeInfo, &'a ConstantAttribute)>,
+    uniforms : Vec<(&'a ShaderUniformInfo, &'a UniformValue)>,
+    textures : Vec<(&'a ShaderUniformInfo, &'a Texture)>,
 }
 
 impl<'a> DrawCall<'a> {
-    pub fn new() -> Self {
+    pub fn new(state : &'a GlState) -> Self {
         DrawCall {
+            state : state,
             program : None,
             indices : None,
-            attribs : [None;16],
-            attrib_count : 0,
+            topology : gl::TRIANGLES,
+            instance_count : None,
+            attribs : Vec::new(),
+            constants : Vec::new(),
+            uniforms : Vec::new(),
+            textures : Vec::new(),
         }
     }
 
@@ -197,45 +682,164 @@ impl<'a> DrawCall<'a> {
         self.indices = Some(indices);
     }
 
+    /// Topology for draws without an index buffer.
+    pub fn set_topology(&mut self, topology : GLenum) {
+        self.topology = topology;
+    }
+
+    /// Draws `count` instances, limited by the per-instance attributes.
+    pub fn set_instance_count(&mut self, count : usize) {
+        self.instance_count = Some(count);
+    }
+
     pub fn set_program(&mut self, program : &'a ShaderProgram) {
         self.program = Some(program);
     }
 
-    pub fn add_attrs(&mut self, name : &'a str, attrs : &'a AttributeBuffer) {
-        assert!(self.attrib_count < 16);
+    fn add_source(&mut self, source : AttributeSource<'a>) -> Result<(), DrawError> {
+        let max = self.state.max_attributes();
+        let used : usize = self.attribs.iter().map(|attr| attr.format.columns).sum();
+        if used + source.format.columns > max {
+            return Err(DrawError::TooManyAttributes(max));
+        }
+
+        self.attribs.push(source);
+        Ok(())
+    }
+
+    pub fn add_attrs(&mut self, name : &'a str, attrs : &'a AttributeBuffer) -> Result<(), DrawError> {
+        self.add_source(AttributeSource {
+            name : name,
+            buffer : attrs.buffer,
+            format : attrs.format,
+            offset : 0,
+            stride : 0,
+            count : attrs.count,
+        })
+    }
+
+    /// Binds `attr` of `buffer` to the input of the same name.
+    pub fn add_interleaved_attr(&mut self, buffer : &'a InterleavedBuffer, attr : &'a InterleavedAttribute) -> Result<(), DrawError> {
+        self.add_source(AttributeSource {
+            name : &attr.name,
+            buffer : buffer.buffer,
+            format : attr.format,
+            offset : attr.offset,
+            stride : attr.stride,
+            count : buffer.get_count(attr),
+        })
+    }
+
+    /// Sets `input` to `value` for every vertex. Inputs without a buffer
+    /// or constant get (0, 0, 0, 1).
+    pub fn add_constant(&mut self, input : &'a ShaderAttributeInfo, value : &'a ConstantAttribute) {
+        self.constants.push((input, value));
+    }
+
+    pub fn add_uniform(&mut self, info : &'a ShaderUniformInfo, value : &'a UniformValue) {
+        self.uniforms.push((info, value));
+    }
 
-        self.attribs[self.attrib_count] = Some((name,attrs));
-        self.attrib_count += 1;
+    /// Binds `texture` to the sampler uniform `info`.
+    pub fn add_texture(&mut self, info : &'a ShaderUniformInfo, texture : &'a Texture) {
+        self.textures.push((info, texture));
     }
 
-    pub fn draw(&self) {
+    pub fn draw(&self) -> Result<(), DrawError> {
+        let prog = match self.program {
+            Some(prog) => prog,
+            None => return Err(DrawError::NoProgram),
+        };
+
+        let max = self.state.max_attributes();
+        let mut locations = Vec::with_capacity(self.attribs.len());
+        for attr in &self.attribs {
+            let input = match prog.get_input(attr.name) {
+                Some(input) => input,
+                None => return Err(DrawError::NoSuchAttribute(attr.name.to_string())),
+            };
+            if input.integer != (attr.format.kind == AttributeKind::Integer) {
+                return Err(DrawError::IntegerMismatch(attr.name.to_string()));
+            }
+            if input.location_count() != attr.format.columns {
+                return Err(DrawError::ColumnMismatch(attr.name.to_string()));
+            }
+            if input.location as usize + attr.format.columns > max {
+                return Err(DrawError::TooManyAttributes(max));
+            }
+            locations.push((input.location, attr));
+        }
+        locations.sort_by_key(|&(index, _)| index);
+
+        prog.bind();
+        for &(info, value) in &self.uniforms {
+            value.upload(info);
+        }
+
         unsafe {
-            let mut should_delete = [true ; 16];
-            if !self.program.is_some() {
-                return;
+            for (unit, &(info, texture)) in self.textures.iter().enumerate() {
+                texture.bind(unit as u32);
+                gl::Uniform1i(info.location, unit as GLint);
+            }
+        }
+
+        let key = VertexArrayKey {
+            program : prog.get_name(),
+            attributes : locations.iter().map(|&(index, attr)| (index, attr.buffer)).collect(),
+            indices : self.indices.map(|indices| indices.buffer).unwrap_or(0),
+        };
+        if !self.state.bind_cached_vertex_array(&key) {
+            self.state.create_vertex_array(key);
+            for &(index, attr) in &locations {
+                self.state.bind_array_buffer(attr.buffer);
+                unsafe {
+                    attr.format.attrib_pointer(index, attr.stride, attr.offset);
+                }
+            }
+            if let Some(indices) = self.indices {
+                indices.bind_to_current_vao();
             }
+        }
 
-            if !self.indices.is_some() {
-                return;
+        //current values are context state, every input without an array needs one
+        let unset = ConstantAttribute::Float(Vec::new());
+        for input in &prog.inputs {
+            if self.attribs.iter().any(|attr| attr.name == input.name) {
+                continue;
             }
-            let prog = self.program.unwrap();
-            prog.bind();
-            for i in 0..self.attrib_count {
-                let (name,attrs) = self.attribs[i].unwrap();
-                let index = prog.get_attr(name);
-                attrs.bind_to_current_vao(index);
-                should_delete[index as usize] = false;
+            match self.constants.iter().find(|&&(info, _)| info.name == input.name) {
+                Some(&(_, value)) => value.upload(input),
+                None => unset.upload(input),
             }
+        }
 
-            for i in 0..16 {
-                if !should_delete[i] {
-                    continue
+        //instances past the end of a per-instance attribute read out of bounds
+        let instances = self.instance_count.map(|count| {
+            self.attribs.iter()
+                .map(|attr| (attr.count, attr.format.divisor as usize))
+                .filter(|&(_, divisor)| divisor > 0)
+                .fold(count, |count, (attr_count, divisor)| count.min(attr_count * divisor))
+        });
+
+        match self.indices {
+            Some(indices) => indices.draw(instances),
+            None => {
+                //draw as many vertices as every per-vertex attribute has
+                let count = self.attribs.iter()
+                    .filter(|attr| attr.format.divisor == 0)
+                    .map(|attr| attr.count)
+                    .min();
+
+                if let Some(count) = count {
+                    unsafe {
+                        match instances {
+                            None => gl::DrawArrays(self.topology, 0, count as GLsizei),
+                            Some(instances) => gl::DrawArraysInstanced(self.topology, 0, count as GLsizei, instances as GLsizei),
+                        }
+                    }
                 }
-                bound_attrs[i] = None;
-                gl::DisableVertexAttribArray(i as GLuint);
             }
-
-            self.indices.unwrap().draw();
         }
+        Ok(())
     }
 }
\ No newline at end of file
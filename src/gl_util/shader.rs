@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::ptr;
-use std::str;
 use std::ops::Drop;
+use std::rc::Rc;
 
 
 use gl;
@@ -8,33 +11,81 @@ use gl::types::*;
 
 
 use std::ffi::CStr;
-use std::ffi::CString;
 
-type ShaderSrc = (&'static [u8], &'static [u8]);
+use super::constant::ConstantAttribute;
+use super::state::GlState;
 
+/// Vertex and fragment source, each nul-terminated.
+pub type ShaderSrc<'a> = (&'a [u8], &'a [u8]);
 
-#[allow(non_upper_case_globals)]
-static mut current_prog : GLuint = 0;
 
 #[derive(Debug)]
 pub struct ShaderProgram {
+    state : Rc<GlState>,
     prog : GLuint,
     pub inputs : Vec<ShaderAttributeInfo>,
+    pub uniforms : Vec<ShaderUniformInfo>,
+    pub defaults : HashMap<String, ConstantAttribute>, //for inputs nothing else provides
 }
 
 impl Drop for ShaderProgram {
 	fn drop(&mut self) {
+		self.state.forget_program(self.prog);
 		unsafe {
 			gl::DeleteProgram(self.prog);
 		}
 	}
 }
 
+/// A program that didn't build, with the driver's log.
+#[derive(Debug)]
+pub enum ShaderError {
+    Compile(String),
+    Link(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::Compile(ref log) => write!(f, "compile failed: {}", log),
+            ShaderError::Link(ref log) => write!(f, "link failed: {}", log),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn description(&self) -> &str {
+        match *self {
+            ShaderError::Compile(_) => "compile failed",
+            ShaderError::Link(_) => "link failed",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ShaderAttributeInfo {
     pub name : String,
     pub location : GLuint,
-    pub dim : usize,
+    pub data_type : GLenum,
+    pub dim : usize, //components per location, rows for matrices
+    pub columns : usize, //matrix columns, 1 for vectors
+    pub size : usize, //array length, 1 for non-arrays
+    pub integer : bool, //int/ivecN/uint/uvecN, needs an integer buffer
+}
+
+impl ShaderAttributeInfo {
+    /// Consecutive locations used from `location` on.
+    pub fn location_count(&self) -> usize {
+        self.columns * self.size
+    }
+}
+
+#[derive(Debug)]
+pub struct ShaderUniformInfo {
+    pub name : String,
+    pub location : GLint,
+    pub data_type : GLenum,
+    pub size : usize, //array length, 1 for non-arrays
 }
 
 unsafe fn get_shader(shader : GLuint, property : GLuint) -> GLint {
@@ -49,17 +100,35 @@ unsafe fn get_program(prog : GLuint, property : GLuint) -> GLint {
     result
 }
 
-unsafe fn check_shader_errors(shader : GLuint) {
-    let status = get_shader(shader, gl::COMPILE_STATUS);
+//the info log, without its nul
+unsafe fn shader_log(shader : GLuint) -> String {
+    let len = (get_shader(shader, gl::INFO_LOG_LENGTH) as usize).max(1);
+    let mut buf = vec![0u8; len];
+    gl::GetShaderInfoLog(shader, len as GLint, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
+    buf.truncate(len - 1);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+unsafe fn program_log(prog : GLuint) -> String {
+    let len = (get_program(prog, gl::INFO_LOG_LENGTH) as usize).max(1);
+    let mut buf = vec![0u8; len];
+    gl::GetProgramInfoLog(prog, len as GLint, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
+    buf.truncate(len - 1);
+    String::from_utf8_lossy(&buf).into_owned()
+}
 
-    if status == gl::FALSE as GLint {
-        println!("err");
-        let len = get_shader(shader, gl::INFO_LOG_LENGTH) as usize;
-        let mut buf = Vec::with_capacity(len);
-        buf.set_len(len -1);
-        gl::GetShaderInfoLog(shader, len as GLint, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
-        println!("{:?}", str::from_utf8(&buf).ok().unwrap());
+//a compiled shader of `kind`, deleted again if it fails
+unsafe fn compile_shader(kind : GLenum, src : &[u8]) -> Result<GLuint, ShaderError> {
+    let shader = gl::CreateShader(kind);
+    assert!(0 != shader);
+    gl::ShaderSource(shader, 1, [src.as_ptr() as *const _].as_ptr(), ptr::null());
+    gl::CompileShader(shader);
+    if gl::TRUE as GLint != get_shader(shader, gl::COMPILE_STATUS) {
+        let log = shader_log(shader);
+        gl::DeleteShader(shader);
+        return Err(ShaderError::Compile(log));
     }
+    Ok(shader)
 }
 
 unsafe fn get_program_iv(prog : GLuint, pname : GLenum) -> GLint {
@@ -68,7 +137,8 @@ unsafe fn get_program_iv(prog : GLuint, pname : GLenum) -> GLint {
     result
 }
 
-unsafe fn get_active_attribute(prog : GLuint, index : GLuint, name_buf : &mut [u8]) -> ShaderAttributeInfo {
+/// None for attribute types we can't feed.
+unsafe fn get_active_attribute(prog : GLuint, index : GLuint, name_buf : &mut [u8]) -> Option<ShaderAttributeInfo> {
     let max_len = name_buf.len() as GLsizei;
     let mut size : GLint = 0;
     let mut data_type : GLenum = 0;
@@ -83,23 +153,85 @@ unsafe fn get_active_attribute(prog : GLuint, index : GLuint, name_buf : &mut [u
         name_buf.as_mut_ptr() as *mut GLchar
     );
 
-    let dim = match data_type {
-        gl::FLOAT => 1,
-        gl::FLOAT_VEC2 => 2,
-        gl::FLOAT_VEC3 => 3,
-        gl::FLOAT_VEC4 => 4,
-        _ => panic!(),
-    };
+    //built-ins like gl_VertexID are active but have no location
+    let location = gl::GetAttribLocation(prog, name_buf.as_ptr() as *const GLchar);
+
+    let mut name = glstr_to_string(name_buf.as_ptr() as *const GLubyte);
+    if name.ends_with("[0]") {
+        let len = name.len() - 3;
+        name.truncate(len);
+    }
+    if location < 0 {
+        return None;
+    }
 
-    assert_eq!(1, size);
+    let (dim, columns, integer) = match data_type {
+        gl::FLOAT => (1, 1, false),
+        gl::FLOAT_VEC2 => (2, 1, false),
+        gl::FLOAT_VEC3 => (3, 1, false),
+        gl::FLOAT_VEC4 => (4, 1, false),
+        gl::FLOAT_MAT2 => (2, 2, false),
+        gl::FLOAT_MAT3 => (3, 3, false),
+        gl::FLOAT_MAT4 => (4, 4, false),
+        gl::FLOAT_MAT2x3 => (3, 2, false),
+        gl::FLOAT_MAT2x4 => (4, 2, false),
+        gl::FLOAT_MAT3x2 => (2, 3, false),
+        gl::FLOAT_MAT3x4 => (4, 3, false),
+        gl::FLOAT_MAT4x2 => (2, 4, false),
+        gl::FLOAT_MAT4x3 => (3, 4, false),
+        gl::INT | gl::UNSIGNED_INT => (1, 1, true),
+        gl::INT_VEC2 | gl::UNSIGNED_INT_VEC2 => (2, 1, true),
+        gl::INT_VEC3 | gl::UNSIGNED_INT_VEC3 => (3, 1, true),
+        gl::INT_VEC4 | gl::UNSIGNED_INT_VEC4 => (4, 1, true),
+        _ => {
+            println!("unsupported type {:#x} for attribute {}", data_type, name);
+            return None;
+        }
+    };
 
-    ShaderAttributeInfo {
-        name : glstr_to_string(name_buf.as_ptr() as *const GLubyte),
-        location : index,
+    Some(ShaderAttributeInfo {
+        name : name,
+        location : location as GLuint,
+        data_type : data_type,
         dim : dim,
-    }
+        columns : columns,
+        size : size as usize,
+        integer : integer,
+    })
 }
 
+unsafe fn get_active_uniform(prog : GLuint, index : GLuint, name_buf : &mut [u8]) -> ShaderUniformInfo {
+    let max_len = name_buf.len() as GLsizei;
+    let mut size : GLint = 0;
+    let mut data_type : GLenum = 0;
+    let mut name_len : GLsizei = 0;
+    gl::GetActiveUniform(
+        prog,
+        index,
+        max_len,
+        &mut name_len,
+        &mut size,
+        &mut data_type,
+        name_buf.as_mut_ptr() as *mut GLchar
+    );
+
+    //-1 for uniforms that live in a uniform block
+    let location = gl::GetUniformLocation(prog, name_buf.as_ptr() as *const GLchar);
+
+    //arrays are reported as "name[0]", look them up by the bare name
+    let mut name = glstr_to_string(name_buf.as_ptr() as *const GLubyte);
+    if name.ends_with("[0]") {
+        let len = name.len() - 3;
+        name.truncate(len);
+    }
+
+    ShaderUniformInfo {
+        name : name,
+        location : location,
+        data_type : data_type,
+        size : size as usize,
+    }
+}
 
 unsafe fn glstr_to_string(ptr : *const GLubyte) -> String {
     if ptr == ptr::null() {
@@ -110,18 +242,16 @@ unsafe fn glstr_to_string(ptr : *const GLubyte) -> String {
 }
 
 impl ShaderProgram {
-	pub fn build_program(src : ShaderSrc) -> ShaderProgram {
+	pub fn build_program(state : &Rc<GlState>, src : ShaderSrc) -> Result<ShaderProgram, ShaderError> {
 		unsafe {
-    		let vs = gl::CreateShader(gl::VERTEX_SHADER);
-    		assert!(0 != vs);
-    		gl::ShaderSource(vs, 1, [src.0.as_ptr() as *const _].as_ptr(), ptr::null());
-    		gl::CompileShader(vs);
-    		check_shader_errors(vs);
-		
-    		let fs = gl::CreateShader(gl::FRAGMENT_SHADER);
-    		gl::ShaderSource(fs, 1, [src.1.as_ptr() as *const _].as_ptr(), ptr::null());
-    		gl::CompileShader(fs);
-    		check_shader_errors(fs);
+    		let vs = compile_shader(gl::VERTEX_SHADER, src.0)?;
+    		let fs = match compile_shader(gl::FRAGMENT_SHADER, src.1) {
+    		    Ok(fs) => fs,
+    		    Err(e) => {
+    		        gl::DeleteShader(vs);
+    		        return Err(e);
+    		    }
+    		};
 		
     		let prog = gl::CreateProgram();
     		assert!(0 != prog);
@@ -138,11 +268,9 @@ impl ShaderProgram {
 		
     		gl::LinkProgram(prog);
     		if gl::TRUE as GLint != get_program(prog, gl::LINK_STATUS) {
-    		    let len = get_program(prog, gl::INFO_LOG_LENGTH) as usize;
-    		    let mut buf = Vec::with_capacity(len);
-    		    buf.set_len(len -1);
-    		    gl::GetProgramInfoLog(prog, len as GLint, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
-    		    println!("{:?}", str::from_utf8(&buf).ok().unwrap());
+    		    let log = program_log(prog);
+    		    gl::DeleteProgram(prog);
+    		    return Err(ShaderError::Link(log));
     		}
 		
     		//query attributes
@@ -156,31 +284,51 @@ impl ShaderProgram {
 		
     		let mut inputs = Vec::with_capacity(input_count as usize);
     		for input_index in 0..input_count {
-    		    let input = get_active_attribute(prog, input_index, &mut input_name_buf[..]);
-    		    println!("{:?}", input);
-    		    inputs.push(input);
+    		    if let Some(input) = get_active_attribute(prog, input_index, &mut input_name_buf[..]) {
+    		        println!("{:?}", input);
+    		        inputs.push(input);
+    		    }
+    		}
+
+    		//query uniforms
+    		let uniform_count = get_program_iv(prog, gl::ACTIVE_UNIFORMS) as u32;
+    		let uniform_name_len = get_program_iv(prog, gl::ACTIVE_UNIFORM_MAX_LENGTH);
+
+    		let mut uniform_name_buf : Vec<u8> = vec![0; uniform_name_len as usize];
+
+    		let mut uniforms = Vec::with_capacity(uniform_count as usize);
+    		for uniform_index in 0..uniform_count {
+    		    let uniform = get_active_uniform(prog, uniform_index, &mut uniform_name_buf[..]);
+    		    println!("{:?}", uniform);
+    		    if uniform.location >= 0 {
+    		        uniforms.push(uniform);
+    		    }
     		}
 		
-    		ShaderProgram {
+    		Ok(ShaderProgram {
+    		    state : state.clone(),
     		    prog : prog,
     		    inputs : inputs,
-    		}
+    		    uniforms : uniforms,
+    		    defaults : HashMap::new(),
+    		})
     	}
     }
 
-    pub fn get_attr(&self, name : &str) -> GLint {
-    	unsafe {
-    		let s = CString::new(name).unwrap();
-    		gl::GetAttribLocation(self.prog, s.as_ptr() as *const _)
-		}
+    /// None if the program has no active attribute called `name`.
+    pub fn get_attr(&self, name : &str) -> Option<GLuint> {
+    	self.get_input(name).map(|input| input.location)
+	}
+
+	pub fn get_input(&self, name : &str) -> Option<&ShaderAttributeInfo> {
+		self.inputs.iter().find(|input| input.name == name)
+	}
+
+	pub fn get_name(&self) -> GLuint {
+		self.prog
 	}
 
 	pub fn bind(&self) {
-		unsafe {
-			if current_prog != self.prog {
-				current_prog = self.prog;
-				gl::UseProgram(self.prog);
-			}
-		}
+		self.state.use_program(self.prog);
 	}
 }
@@ -0,0 +1,168 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use gl;
+use gl::types::*;
+
+//never a GL name, so the binding after `invalidate` always goes through
+const UNKNOWN : GLuint = !0;
+
+/// What a vertex array object was set up with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VertexArrayKey {
+    pub program : GLuint,
+    pub attributes : Vec<(GLuint, GLuint)>, //(location, buffer)
+    pub indices : GLuint, //0 for none
+}
+
+impl VertexArrayKey {
+    fn uses_buffer(&self, buffer : GLuint) -> bool {
+        self.indices == buffer || self.attributes.iter().any(|&(_, b)| b == buffer)
+    }
+}
+
+/// The bindings of one GL context, so redundant binds can be skipped, and
+/// the vertex array objects cached for its draws.
+///
+/// Every object created in the context keeps a reference, and forgets its
+/// bindings and vertex arrays here when it is refilled or deleted, so a
+/// reused name is never mistaken for the old object.
+#[derive(Debug)]
+pub struct GlState {
+    program : Cell<GLuint>,
+    array_buffer : Cell<GLuint>,
+    vertex_array : Cell<GLuint>,
+    vertex_arrays : RefCell<HashMap<VertexArrayKey, GLuint>>,
+    max_attributes : usize,
+}
+
+impl Drop for GlState {
+    fn drop(&mut self) {
+        for (_, vao) in self.vertex_arrays.borrow_mut().drain() {
+            unsafe {
+                gl::DeleteVertexArrays(1, &vao);
+            }
+        }
+    }
+}
+
+impl GlState {
+    /// Call with the context current, once GL is loaded.
+    pub fn new() -> Rc<GlState> {
+        let max_attributes = unsafe {
+            let mut max : GLint = 0;
+            gl::GetIntegerv(gl::MAX_VERTEX_ATTRIBS, &mut max);
+            max as usize
+        };
+
+        Rc::new(GlState {
+            program : Cell::new(0),
+            array_buffer : Cell::new(0),
+            vertex_array : Cell::new(0),
+            vertex_arrays : RefCell::new(HashMap::new()),
+            max_attributes : max_attributes,
+        })
+    }
+
+    /// Forgets the cached bindings, so the next binds are issued even if they
+    /// look redundant. Call after other code has used the context. Vertex
+    /// arrays stay cached.
+    pub fn invalidate(&self) {
+        self.program.set(UNKNOWN);
+        self.array_buffer.set(UNKNOWN);
+        self.vertex_array.set(UNKNOWN);
+    }
+
+    /// GL_MAX_VERTEX_ATTRIBS, at least 16.
+    pub fn max_attributes(&self) -> usize {
+        self.max_attributes
+    }
+
+    pub fn use_program(&self, prog : GLuint) {
+        if self.program.get() != prog {
+            self.program.set(prog);
+            unsafe {
+                gl::UseProgram(prog);
+            }
+        }
+    }
+
+    pub fn bind_array_buffer(&self, buffer : GLuint) {
+        if self.array_buffer.get() != buffer {
+            self.array_buffer.set(buffer);
+            unsafe {
+                gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+            }
+        }
+    }
+
+    pub fn bind_vertex_array(&self, vao : GLuint) {
+        if self.vertex_array.get() != vao {
+            self.vertex_array.set(vao);
+            unsafe {
+                gl::BindVertexArray(vao);
+            }
+        }
+    }
+
+    /// Binds the vertex array cached for `key`. False if there is none yet.
+    pub fn bind_cached_vertex_array(&self, key : &VertexArrayKey) -> bool {
+        let vao = self.vertex_arrays.borrow().get(key).cloned();
+        match vao {
+            Some(vao) => {
+                self.bind_vertex_array(vao);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Creates and binds an empty vertex array, cached for `key`. The caller
+    /// sets up its attributes and element buffer.
+    pub fn create_vertex_array(&self, key : VertexArrayKey) {
+        let vao = unsafe {
+            let mut vao = mem::uninitialized();
+            gl::GenVertexArrays(1, &mut vao);
+            vao
+        };
+        self.bind_vertex_array(vao);
+        self.vertex_arrays.borrow_mut().insert(key, vao);
+    }
+
+    fn delete_vertex_arrays<F>(&self, mut stale : F) where F : FnMut(&VertexArrayKey) -> bool {
+        let mut vertex_arrays = self.vertex_arrays.borrow_mut();
+        let keys : Vec<VertexArrayKey> = vertex_arrays.keys().filter(|key| stale(key)).cloned().collect();
+        for key in keys {
+            let vao = vertex_arrays.remove(&key).unwrap();
+            if self.vertex_array.get() == vao {
+                self.vertex_array.set(0);
+            }
+            unsafe {
+                gl::DeleteVertexArrays(1, &vao);
+            }
+        }
+    }
+
+    /// Call when `buffer` is refilled, its layout may have changed.
+    pub fn buffer_changed(&self, buffer : GLuint) {
+        self.delete_vertex_arrays(|key| key.uses_buffer(buffer));
+    }
+
+    /// Call when `buffer` is deleted.
+    pub fn forget_buffer(&self, buffer : GLuint) {
+        if self.array_buffer.get() == buffer {
+            self.array_buffer.set(0);
+        }
+        self.buffer_changed(buffer);
+    }
+
+    /// Call when `prog` is deleted.
+    pub fn forget_program(&self, prog : GLuint) {
+        if self.program.get() == prog {
+            self.program.set(0);
+        }
+        self.delete_vertex_arrays(|key| key.program == prog);
+    }
+}
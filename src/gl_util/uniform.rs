@@ -0,0 +1,79 @@
+use gl;
+use gl::types::*;
+
+use super::shader::ShaderUniformInfo;
+
+/// A value for a uniform, stored until a draw uploads it to its program.
+/// Matrices are column major.
+#[derive(Debug, Clone)]
+pub enum UniformValue {
+    Float(usize, Vec<f32>), //components per element, 1-4
+    Matrix(usize, Vec<f32>), //columns (and rows), 2-4
+    Int(usize, Vec<i32>), //components per element, 1-4
+}
+
+impl UniformValue {
+    fn element_len(&self) -> usize {
+        match *self {
+            UniformValue::Float(dim, _) => dim,
+            UniformValue::Matrix(dim, _) => dim * dim,
+            UniformValue::Int(dim, _) => dim,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            UniformValue::Float(_, ref data) => data.len(),
+            UniformValue::Matrix(_, ref data) => data.len(),
+            UniformValue::Int(_, ref data) => data.len(),
+        }
+    }
+
+    pub fn matches(&self, data_type : GLenum) -> bool {
+        match (self, data_type) {
+            (&UniformValue::Float(1, _), gl::FLOAT) => true,
+            (&UniformValue::Float(2, _), gl::FLOAT_VEC2) => true,
+            (&UniformValue::Float(3, _), gl::FLOAT_VEC3) => true,
+            (&UniformValue::Float(4, _), gl::FLOAT_VEC4) => true,
+            (&UniformValue::Matrix(2, _), gl::FLOAT_MAT2) => true,
+            (&UniformValue::Matrix(3, _), gl::FLOAT_MAT3) => true,
+            (&UniformValue::Matrix(4, _), gl::FLOAT_MAT4) => true,
+            (&UniformValue::Int(1, _), gl::INT) | (&UniformValue::Int(1, _), gl::BOOL) => true,
+            (&UniformValue::Int(2, _), gl::INT_VEC2) | (&UniformValue::Int(2, _), gl::BOOL_VEC2) => true,
+            (&UniformValue::Int(3, _), gl::INT_VEC3) | (&UniformValue::Int(3, _), gl::BOOL_VEC3) => true,
+            (&UniformValue::Int(4, _), gl::INT_VEC4) | (&UniformValue::Int(4, _), gl::BOOL_VEC4) => true,
+            _ => false,
+        }
+    }
+
+    /// Uploads to the currently bound program.
+    pub fn upload(&self, info : &ShaderUniformInfo) {
+        if !self.matches(info.data_type) {
+            println!("uniform {} doesn't match shader type {:X}", info.name, info.data_type);
+            return;
+        }
+
+        let count = (self.len() / self.element_len()).min(info.size) as GLsizei;
+        if count == 0 {
+            return;
+        }
+
+        let loc = info.location;
+        unsafe {
+            match *self {
+                UniformValue::Float(1, ref data) => gl::Uniform1fv(loc, count, data.as_ptr()),
+                UniformValue::Float(2, ref data) => gl::Uniform2fv(loc, count, data.as_ptr()),
+                UniformValue::Float(3, ref data) => gl::Uniform3fv(loc, count, data.as_ptr()),
+                UniformValue::Float(4, ref data) => gl::Uniform4fv(loc, count, data.as_ptr()),
+                UniformValue::Matrix(2, ref data) => gl::UniformMatrix2fv(loc, count, gl::FALSE, data.as_ptr()),
+                UniformValue::Matrix(3, ref data) => gl::UniformMatrix3fv(loc, count, gl::FALSE, data.as_ptr()),
+                UniformValue::Matrix(4, ref data) => gl::UniformMatrix4fv(loc, count, gl::FALSE, data.as_ptr()),
+                UniformValue::Int(1, ref data) => gl::Uniform1iv(loc, count, data.as_ptr()),
+                UniformValue::Int(2, ref data) => gl::Uniform2iv(loc, count, data.as_ptr()),
+                UniformValue::Int(3, ref data) => gl::Uniform3iv(loc, count, data.as_ptr()),
+                UniformValue::Int(4, ref data) => gl::Uniform4iv(loc, count, data.as_ptr()),
+                _ => unreachable!(), //rejected by matches
+            }
+        }
+    }
+}
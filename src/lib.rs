@@ -0,0 +1,40 @@
+//! Renders command streams from mzmr publishers with OpenGL.
+//!
+//! `gl_util` wraps the GL objects (buffers, programs, textures). `Renderer`
+//! applies `Command`s to them and can be driven by any transport; the
+//! `eyes` binary feeds it from zmq through `Transport`. To draw inside an
+//! existing context, make it current, call `gl_util::init_gl` with its
+//! loader, create a `Renderer` and present after each `FinishedFrame`. Call
+//! `Renderer::invalidate` after GL calls of your own on that context.
+
+extern crate glutin;
+extern crate gl;
+extern crate libc;
+extern crate zmq;
+extern crate protobuf;
+extern crate mzmr_proto;
+extern crate rand;
+extern crate time;
+extern crate prost;
+#[macro_use]
+extern crate prost_derive;
+extern crate clap;
+extern crate toml;
+extern crate png;
+extern crate libloading;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod gl_util;
+pub mod commands;
+pub mod renderer;
+pub mod transport;
+pub mod config;
+pub mod egl;
+pub mod surface;
+pub mod capture;
+pub mod reply;
+pub mod record;
+
+pub use renderer::{Renderer, FinishedFrame};
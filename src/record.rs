@@ -0,0 +1,193 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use prost::Message;
+use prost::encoding;
+use time;
+
+use mzmr_proto::cmd::*;
+use mzmr_proto::cmd::command::*;
+
+/// One received command, as stored in a recording.
+#[derive(Clone, PartialEq, Message)]
+pub struct Record {
+    /// Receive time, nanoseconds since the unix epoch.
+    #[prost(uint64, tag="1")]
+    pub timestamp_ns : u64,
+    #[prost(bytes, tag="2")]
+    pub prefix : Vec<u8>,
+    #[prost(message, optional, tag="3")]
+    pub command : Option<Command>,
+}
+
+fn now_ns() -> u64 {
+    let now = time::get_time();
+    (now.sec as u64) * 1_000_000_000 + (now.nsec as u64)
+}
+
+/// Writes received commands to a file as length-delimited `Record`s.
+pub struct Recorder {
+    file : BufWriter<File>,
+    buf : Vec<u8>,
+}
+
+impl Recorder {
+    pub fn create(path : &str) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file : BufWriter::new(File::create(path)?),
+            buf : Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, prefix : &[u8], command : &Command) -> io::Result<()> {
+        //the fields of a `Record`, encoded by hand so the command isn't
+        //copied into one first
+        let timestamp_ns = now_ns();
+        let prefix = prefix.to_vec();
+        let len = encoding::uint64::encoded_len(1, &timestamp_ns)
+            + encoding::bytes::encoded_len(2, &prefix)
+            + encoding::message::encoded_len(3, command);
+
+        self.buf.clear();
+        encoding::encode_varint(len as u64, &mut self.buf);
+        encoding::uint64::encode(1, &timestamp_ns, &mut self.buf);
+        encoding::bytes::encode(2, &prefix, &mut self.buf);
+        encoding::message::encode(3, command, &mut self.buf);
+        self.file.write_all(&self.buf[..])?;
+
+        //keep whole frames on disk in case the viewer dies
+        if let Some(CmdData::Done(_)) = command.cmd_data {
+            self.file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+//timestamp, prefix length and field tags around a message
+const RECORD_OVERHEAD : usize = 32;
+
+/// Reads the `Record`s written by a `Recorder`, in order.
+pub struct RecordReader {
+    file : BufReader<File>,
+    buf : Vec<u8>,
+    max_len : usize,
+}
+
+impl RecordReader {
+    /// Records holding a message over `max_message_size` bytes are rejected
+    /// as corrupt rather than allocated.
+    pub fn open(path : &str, max_message_size : usize) -> io::Result<RecordReader> {
+        Ok(RecordReader {
+            file : BufReader::new(File::open(path)?),
+            buf : Vec::new(),
+            max_len : max_message_size.saturating_add(RECORD_OVERHEAD),
+        })
+    }
+
+    //None at the end of the file
+    fn read_len(&mut self) -> io::Result<Option<usize>> {
+        let mut len : u64 = 0;
+        for shift in 0..10 {
+            let mut byte = [0u8];
+            if self.file.read(&mut byte)? == 0 {
+                if shift == 0 {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record length"));
+            }
+            len |= ((byte[0] & 0x7F) as u64) << (shift * 7);
+            if byte[0] & 0x80 == 0 {
+                return Ok(Some(len as usize));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "bad record length"))
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        let len = match self.read_len() {
+            Ok(Some(len)) => len,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        if len > self.max_len {
+            return Some(Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} byte record is over the limit", len))));
+        }
+
+        self.buf.resize(len, 0);
+        if let Err(e) = self.file.read_exact(&mut self.buf[..]) {
+            return Some(Err(e));
+        }
+
+        Some(Record::decode(&self.buf[..]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{ErrorKind, Write};
+    use std::process;
+
+    use mzmr_proto::cmd::*;
+    use mzmr_proto::cmd::command::*;
+
+    use super::{Recorder, RecordReader};
+
+    fn temp_path(name : &str) -> String {
+        let path = env::temp_dir().join(format!("eyes-{}-{}", process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn done(frame_number : u32) -> Command {
+        Command { cmd_data : Some(CmdData::Done(Done { frame_number : frame_number })) }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round_trip.log");
+        //over 127 bytes so the length takes two varint bytes
+        let long_prefix = vec![b'x'; 300];
+        {
+            let mut recorder = Recorder::create(&path).unwrap();
+            recorder.write(b"render/", &done(1)).unwrap();
+            recorder.write(&long_prefix[..], &done(2)).unwrap();
+        }
+
+        let records : Vec<_> = RecordReader::open(&path, 1024).unwrap().map(|r| r.unwrap()).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!(b"render/".to_vec(), records[0].prefix);
+        assert_eq!(Some(done(1)), records[0].command);
+        assert_eq!(long_prefix, records[1].prefix);
+        assert_eq!(Some(done(2)), records[1].command);
+        assert!(records[0].timestamp_ns <= records[1].timestamp_ns);
+    }
+
+    #[test]
+    fn oversized_length_is_invalid() {
+        let path = temp_path("oversized.log");
+        //varint for 2^62
+        File::create(&path).unwrap().write_all(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x40]).unwrap();
+
+        let result = RecordReader::open(&path, 1024).unwrap().next().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ErrorKind::InvalidData, result.unwrap_err().kind());
+    }
+
+    #[test]
+    fn truncated_length_is_eof() {
+        let path = temp_path("truncated.log");
+        File::create(&path).unwrap().write_all(&[0x80]).unwrap();
+
+        let result = RecordReader::open(&path, 1024).unwrap().next().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ErrorKind::UnexpectedEof, result.unwrap_err().kind());
+    }
+}
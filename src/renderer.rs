@@ -0,0 +1,184 @@
+use gl;
+
+use mzmr_proto::cmd::*;
+use mzmr_proto::cmd::command::*;
+
+use commands::Resources;
+
+/// A one line description of `cmd` for logs, without its payload.
+pub fn describe(cmd : &Command) -> String {
+    match cmd.cmd_data {
+        None => "empty".to_string(),
+        Some(CmdData::Done(ref done)) => format!("done {}", done.frame_number),
+        Some(CmdData::Capture(ref capture)) => format!("capture {:?}", capture.path),
+        Some(CmdData::Screenshot(_)) => "screenshot".to_string(),
+        Some(CmdData::SetResource(ref set_resource)) => format!("set {}/{}", set_resource.namespace, set_resource.name),
+        Some(CmdData::Draw(ref draw)) => format!("draw {}/{}", draw.program_namespace, draw.program_name),
+        Some(CmdData::Delete(ref delete)) => format!("delete {}/{}", delete.namespace, delete.name),
+        Some(CmdData::Reset(_)) => "reset".to_string(),
+    }
+}
+
+/// Returned by `Renderer::apply` when a Done command ends a frame.
+#[derive(Debug)]
+pub struct FinishedFrame {
+    pub frame_number : u32,
+    /// Paths from Capture commands, empty for a numbered file.
+    pub captures : Vec<String>,
+    /// Formats from Screenshot commands.
+    pub screenshots : Vec<i32>,
+}
+
+/// Frame numbering and the captures and screenshots requested for the frame
+/// in progress. Kept apart from GL so it can be tested.
+#[derive(Debug)]
+struct FrameState {
+    prefixes : Vec<String>,
+    current_frame : Option<u32>,
+    captures : Vec<String>,
+    screenshots : Vec<i32>,
+}
+
+impl FrameState {
+    fn new(prefixes : Vec<String>) -> FrameState {
+        FrameState {
+            prefixes : prefixes,
+            current_frame : None,
+            captures : Vec::new(),
+            screenshots : Vec::new(),
+        }
+    }
+
+    fn frame_topics(&self) -> Vec<String> {
+        match self.current_frame {
+            Some(frame) => self.prefixes.iter().map(|prefix| format!("{}frame/{}/", prefix, frame)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn done(&mut self, frame_number : u32) -> FinishedFrame {
+        self.current_frame = Some(frame_number);
+        FinishedFrame {
+            frame_number : frame_number,
+            captures : self.captures.drain(..).collect(),
+            screenshots : self.screenshots.drain(..).collect(),
+        }
+    }
+}
+
+/// Applies commands to GL state, independent of where they come from.
+///
+/// Frames are delimited by Done commands. The transport should subscribe to
+/// `frame_topics()` after each finished frame.
+pub struct Renderer {
+    resources : Resources,
+    frame : FrameState,
+}
+
+impl Renderer {
+    /// `prefixes` are the topic prefixes frame topics are derived from.
+    /// Needs a current GL context.
+    pub fn new(prefixes : Vec<String>) -> Renderer {
+        Renderer {
+            resources : Resources::new(),
+            frame : FrameState::new(prefixes),
+        }
+    }
+
+    pub fn current_frame(&self) -> Option<u32> {
+        self.frame.current_frame
+    }
+
+    /// Topics carrying the current frame's commands.
+    pub fn frame_topics(&self) -> Vec<String> {
+        self.frame.frame_topics()
+    }
+
+    /// Resyncs the cached GL bindings. Call after making GL calls of your
+    /// own on the same context; `clear` does it every frame.
+    pub fn invalidate(&self) {
+        self.resources.invalidate();
+    }
+
+    /// Starts a new frame.
+    pub fn clear(&self) {
+        self.invalidate();
+        unsafe {
+            gl::ClearColor(0.0,0.0,0.0,1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+    }
+
+    /// `prefix` is the topic `cmd` arrived on. Nothing is logged here, see
+    /// `describe`.
+    pub fn apply(&mut self, _prefix : &[u8], cmd : Command) -> Option<FinishedFrame> {
+        match cmd.cmd_data {
+            None => None,
+            Some(CmdData::Done(Done { frame_number, ..})) => {
+                Some(self.frame.done(frame_number))
+            },
+            Some(CmdData::Capture(Capture { path })) => {
+                self.frame.captures.push(path);
+                None
+            },
+            Some(CmdData::Screenshot(Screenshot { format })) => {
+                //sent once the frame is done
+                self.frame.screenshots.push(format);
+                None
+            },
+            Some(CmdData::SetResource(set_resource)) => {
+                self.resources.set_resource(set_resource);
+                None
+            },
+            Some(CmdData::Draw(draw)) => {
+                self.resources.draw(draw);
+                None
+            },
+            Some(CmdData::Delete(Delete { name, namespace })) => {
+                self.resources.delete(&name, &namespace);
+                None
+            },
+            Some(CmdData::Reset(_)) => {
+                self.resources.clear();
+                None
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameState;
+
+    #[test]
+    fn no_frame_topics_before_the_first_frame() {
+        let frame = FrameState::new(vec!["render/".to_string()]);
+        assert_eq!(None, frame.current_frame);
+        assert!(frame.frame_topics().is_empty());
+    }
+
+    #[test]
+    fn frame_topics_follow_the_last_done() {
+        let mut frame = FrameState::new(vec!["render/".to_string(), "debug/".to_string()]);
+        frame.done(7);
+        assert_eq!(Some(7), frame.current_frame);
+        assert_eq!(vec!["render/frame/7/".to_string(), "debug/frame/7/".to_string()], frame.frame_topics());
+    }
+
+    #[test]
+    fn done_hands_over_requests_once() {
+        let mut frame = FrameState::new(Vec::new());
+        frame.captures.push("a.png".to_string());
+        frame.captures.push(String::new());
+        frame.screenshots.push(1);
+
+        let finished = frame.done(3);
+        assert_eq!(3, finished.frame_number);
+        assert_eq!(vec!["a.png".to_string(), String::new()], finished.captures);
+        assert_eq!(vec![1], finished.screenshots);
+
+        let next = frame.done(4);
+        assert!(next.captures.is_empty());
+        assert!(next.screenshots.is_empty());
+    }
+}
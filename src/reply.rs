@@ -0,0 +1,170 @@
+use glutin;
+use glutin::{ElementState, MouseScrollDelta, WindowEvent};
+
+use mzmr_proto::socket::*;
+use mzmr_proto::cmd::*;
+use mzmr_proto::cmd::reply::*;
+use mzmr_proto::cmd::input::Event;
+
+use capture::encode_png;
+use config::Config;
+use gl_util::read_pixels;
+
+/// Publishes replies and input events from this viewer, each on a topic
+/// starting with the instance name.
+pub struct ReplySender {
+    socket : Option<XPub<Reply,Reply>>,
+    instance_name : String,
+}
+
+/// Left, right and middle are 0, 1 and 2, other buttons follow from 3.
+fn mouse_button(button : glutin::MouseButton) -> u32 {
+    match button {
+        glutin::MouseButton::Left => 0,
+        glutin::MouseButton::Right => 1,
+        glutin::MouseButton::Middle => 2,
+        glutin::MouseButton::Other(n) => 3 + n as u32,
+    }
+}
+
+fn input_event(event : &WindowEvent) -> Option<Event> {
+    match *event {
+        WindowEvent::KeyboardInput { input, .. } => Some(Event::Key(Key {
+            scancode : input.scancode,
+            key : input.virtual_keycode.map(|key| format!("{:?}", key)).unwrap_or_default(),
+            pressed : input.state == ElementState::Pressed,
+            shift : input.modifiers.shift,
+            ctrl : input.modifiers.ctrl,
+            alt : input.modifiers.alt,
+            logo : input.modifiers.logo,
+        })),
+        WindowEvent::MouseMoved { position : (x, y), .. } => Some(Event::MouseMove(MouseMove {
+            x : x,
+            y : y,
+        })),
+        WindowEvent::MouseInput { state, button, .. } => Some(Event::MouseButton(MouseButton {
+            button : mouse_button(button),
+            pressed : state == ElementState::Pressed,
+        })),
+        WindowEvent::MouseWheel { delta, .. } => {
+            let (dx, dy, pixels) = match delta {
+                MouseScrollDelta::LineDelta(dx, dy) => (dx, dy, false),
+                MouseScrollDelta::PixelDelta(dx, dy) => (dx, dy, true),
+            };
+            Some(Event::Scroll(Scroll {
+                dx : dx,
+                dy : dy,
+                pixels : pixels,
+            }))
+        },
+        WindowEvent::Focused(focused) => Some(Event::Focus(Focus {
+            focused : focused,
+        })),
+        WindowEvent::Resized(width, height) => Some(Event::Resize(Resize {
+            width : width,
+            height : height,
+        })),
+        _ => None,
+    }
+}
+
+impl ReplySender {
+    pub fn new(ctx : &Context, config : &Config, instance_name : &str) -> ReplySender {
+        let socket = if config.reply.is_empty() {
+            None
+        } else {
+            let mut socket : XPub<Reply,Reply> = ctx.xpub().unwrap();
+            for endpoint in &config.reply {
+                socket.connect(endpoint).unwrap();
+                println!("replying to {}", endpoint);
+            }
+            Some(socket)
+        };
+
+        ReplySender {
+            socket : socket,
+            instance_name : instance_name.to_string(),
+        }
+    }
+
+    fn send(&mut self, kind : &str, frame_number : u32, reply_data : ReplyData) {
+        let socket = match self.socket {
+            Some(ref mut socket) => socket,
+            None => {
+                println!("no reply endpoint for {}", kind);
+                return;
+            }
+        };
+
+        let topic = format!("{}/{}/", self.instance_name, kind);
+        let reply = Reply {
+            instance_name : self.instance_name.clone(),
+            frame_number : frame_number,
+            reply_data : Some(reply_data),
+        };
+        if let Err(e) = socket.topic(topic.as_bytes()).send(&reply) {
+            println!("sending {} failed: {:?}", kind, e);
+        }
+    }
+
+    /// Publishes keyboard, mouse, focus and resize events under
+    /// <instance name>/input/. Other events are ignored.
+    pub fn send_input(&mut self, frame_number : u32, event : &WindowEvent) {
+        if self.socket.is_none() {
+            return;
+        }
+        if let Some(event) = input_event(event) {
+            self.send("input", frame_number, ReplyData::Input(Input {
+                event : Some(event),
+            }));
+        }
+    }
+
+    /// Reads back the finished frame and publishes it. Call before the frame
+    /// is presented.
+    pub fn send_screenshot(&mut self, frame_number : u32, format : i32, size : (u32, u32)) {
+        let (width, height) = size;
+        if width == 0 || height == 0 {
+            println!("skipping screenshot of frame {}, the framebuffer is {}x{}", frame_number, width, height);
+            return;
+        }
+        let pixels = read_pixels(width, height);
+        let data = match screenshot::Format::from_i32(format) {
+            Some(screenshot::Format::Raw) => pixels,
+            Some(screenshot::Format::Png) => {
+                let mut encoded = Vec::new();
+                if let Err(e) = encode_png(&mut encoded, width, height, &pixels[..]) {
+                    println!("screenshot encoding failed: {}", e);
+                    return;
+                }
+                encoded
+            },
+            None => {
+                println!("unknown screenshot format {}", format);
+                return;
+            }
+        };
+
+        self.send("screenshot", frame_number, ReplyData::Image(Image {
+            width : width,
+            height : height,
+            format : format,
+            data : data,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glutin::MouseButton;
+    use super::mouse_button;
+
+    #[test]
+    fn other_buttons_follow_middle() {
+        assert_eq!(0, mouse_button(MouseButton::Left));
+        assert_eq!(1, mouse_button(MouseButton::Right));
+        assert_eq!(2, mouse_button(MouseButton::Middle));
+        assert_eq!(3, mouse_button(MouseButton::Other(0)));
+        assert_eq!(5, mouse_button(MouseButton::Other(2)));
+    }
+}
@@ -0,0 +1,98 @@
+use gl;
+use glutin;
+use glutin::GlContext;
+
+use config::Config;
+use egl::EglContext;
+use gl_util::*;
+
+/// Where frames end up: a window, or an offscreen framebuffer in a
+/// headless context.
+///
+/// Headless contexts come from EGL (see `EglContext`), which needs libEGL
+/// but no display, so Mesa's llvmpipe works on servers without a GPU.
+pub enum Surface {
+    Window {
+        event_loop : glutin::EventsLoop,
+        window : glutin::GlWindow,
+    },
+    Headless {
+        context : EglContext,
+        target : Framebuffer,
+    },
+}
+
+impl Surface {
+    /// Creates the context, makes it current and loads GL.
+    pub fn new(config : &Config) -> Result<Surface, String> {
+        if config.headless {
+            let context = EglContext::new(config.width, config.height)
+                .map_err(|e| format!("can't create a headless context: {}", e))?;
+
+            init_gl(|symbol| context.get_proc_address(symbol) as *const _);
+
+            //render into our own RGBA8 target rather than whatever buffer
+            //the headless backend provides, so captures look the same
+            let target = Framebuffer::new(config.width, config.height);
+            target.bind();
+
+            Ok(Surface::Headless {
+                context : context,
+                target : target,
+            })
+        } else {
+            let event_loop = glutin::EventsLoop::new();
+
+            let window = glutin::WindowBuilder::new()
+                .with_title(config.title.clone())
+                .with_dimensions(config.width, config.height);
+
+            let context = glutin::ContextBuilder::new()
+                .with_gl_profile(glutin::GlProfile::Core)
+                .with_vsync(config.vsync);
+
+            let window = glutin::GlWindow::new(window, context, &event_loop)
+                .map_err(|e| format!("can't create a window: {}", e))?;
+
+            unsafe {
+                let _ = window.make_current();
+            }
+            init_gl(|symbol| window.get_proc_address(symbol) as *const _);
+
+            Ok(Surface::Window {
+                event_loop : event_loop,
+                window : window,
+            })
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        match *self {
+            Surface::Window { .. } => false,
+            Surface::Headless { .. } => true,
+        }
+    }
+
+    pub fn poll_events<F>(&mut self, callback : F) where F : FnMut(glutin::Event) {
+        match *self {
+            Surface::Window { ref mut event_loop, .. } => event_loop.poll_events(callback),
+            Surface::Headless { .. } => (),
+        }
+    }
+
+    /// Size of the framebuffer being rendered to, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        match *self {
+            Surface::Window { ref window, .. } => window.get_inner_size_pixels().unwrap_or((0, 0)),
+            Surface::Headless { ref target, .. } => target.get_size(),
+        }
+    }
+
+    /// Shows the finished frame. Waits for VBLANK when windowed with vsync.
+    pub fn present(&self) {
+        match *self {
+            Surface::Window { ref window, .. } => window.swap_buffers().unwrap(),
+            Surface::Headless { .. } => unsafe { gl::Finish() },
+        }
+    }
+}
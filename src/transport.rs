@@ -0,0 +1,145 @@
+use mzmr_proto::socket::*;
+use mzmr_proto::cmd::*;
+
+use zmq;
+
+use config::Config;
+use record::Recorder;
+
+//how far past max_message_size zmq lets a message through
+const HARD_LIMIT_FACTOR : usize = 16;
+
+/// Receives commands from publishers over zmq, optionally recording them.
+pub struct Transport {
+    socket : XSub<Command,Command>,
+    msg : zmq::Message,
+    max_message_size : usize,
+    frame_topics : Vec<String>,
+    recorder : Option<Recorder>,
+}
+
+impl Transport {
+    /// Fails if the recording can't be created.
+    pub fn connect(ctx : &Context, config : &Config, instance_name : &str) -> Result<Transport, String> {
+        let mut socket : XSub<Command,Command> = ctx.xsub().unwrap();
+        //max_message_size is checked in recv, where it can be reported. zmq
+        //disconnects a peer over MAXMSGSIZE without a word, so that is only
+        //a hard cap against messages too big to allocate. Set before
+        //connecting, it only applies to new connections.
+        let hard_limit = config.max_message_size.saturating_mul(HARD_LIMIT_FACTOR).min(i64::max_value() as usize);
+        socket.zmq_socket().set_maxmsgsize(hard_limit as i64).unwrap();
+        for prefix in &config.subscribe {
+            socket.subscribe(prefix.as_bytes()).unwrap(); //subscribe for all
+        }
+        socket.subscribe(instance_name.as_bytes()).unwrap(); //subscribe for direct
+        for endpoint in &config.connect {
+            socket.connect(endpoint).unwrap();
+            println!("connected {}", endpoint);
+        }
+
+        let recorder = match config.record {
+            Some(ref path) => {
+                println!("recording to {}", path);
+                Some(Recorder::create(path).map_err(|e| format!("can't record to {}: {}", path, e))?)
+            },
+            None => None,
+        };
+
+        Ok(Transport {
+            socket : socket,
+            msg : zmq::Message::new().unwrap(),
+            max_message_size : config.max_message_size,
+            frame_topics : Vec::new(),
+            recorder : recorder,
+        })
+    }
+
+    pub fn can_recv(&mut self) -> bool {
+        self.socket.can_recv().unwrap()
+    }
+
+    /// Receives the next command. Messages over the size limit and ones that
+    /// don't decode are reported and dropped.
+    pub fn recv(&mut self) -> Option<(&[u8], Command)> {
+        if let Err(e) = self.socket.recv_msg(&mut self.msg) {
+            println!("receive failed: {:?}", e);
+            return None;
+        }
+
+        if self.msg.len() > self.max_message_size {
+            println!("dropping {} byte message, the limit is {} bytes", self.msg.len(), self.max_message_size);
+            return None;
+        }
+
+        let (prefix, cmd) = match self.socket.decode(&self.msg[..]) {
+            Ok(received) => received,
+            Err(e) => {
+                println!("bad command: {:?}", e);
+                return None;
+            }
+        };
+
+        if let Some(ref mut recorder) = self.recorder {
+            if let Err(e) = recorder.write(prefix, &cmd) {
+                println!("recording failed: {}", e);
+            }
+        }
+        Some((prefix, cmd))
+    }
+
+    /// Swaps the per-frame subscriptions for `topics`.
+    pub fn set_frame_topics(&mut self, topics : Vec<String>) {
+        {
+            let (unsubscribe, subscribe) = topic_changes(&self.frame_topics[..], &topics[..]);
+            for topic in unsubscribe {
+                self.socket.unsubscribe(topic.as_bytes()).unwrap();
+            }
+            for topic in subscribe {
+                self.socket.subscribe(topic.as_bytes()).unwrap();
+            }
+        }
+        self.frame_topics = topics;
+    }
+}
+
+//topics to unsubscribe from and to subscribe to, to go from `old` to `new`
+fn topic_changes<'a>(old : &'a [String], new : &'a [String]) -> (Vec<&'a String>, Vec<&'a String>) {
+    let unsubscribe = old.iter().filter(|topic| !new.contains(*topic)).collect();
+    let subscribe = new.iter().filter(|topic| !old.contains(*topic)).collect();
+    (unsubscribe, subscribe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::topic_changes;
+
+    fn topics(names : &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn first_frame_subscribes_to_everything() {
+        let new = topics(&["render/frame/1/", "debug/frame/1/"]);
+        let (unsubscribe, subscribe) = topic_changes(&[], &new[..]);
+        assert!(unsubscribe.is_empty());
+        assert_eq!(vec![&new[0], &new[1]], subscribe);
+    }
+
+    #[test]
+    fn next_frame_swaps_topics() {
+        let old = topics(&["render/frame/1/", "debug/frame/1/"]);
+        let new = topics(&["render/frame/2/", "debug/frame/2/"]);
+        let (unsubscribe, subscribe) = topic_changes(&old[..], &new[..]);
+        assert_eq!(vec![&old[0], &old[1]], unsubscribe);
+        assert_eq!(vec![&new[0], &new[1]], subscribe);
+    }
+
+    #[test]
+    fn kept_topics_are_left_alone() {
+        let old = topics(&["render/frame/1/", "debug/frame/1/"]);
+        let new = topics(&["render/frame/1/"]);
+        let (unsubscribe, subscribe) = topic_changes(&old[..], &new[..]);
+        assert_eq!(vec![&old[1]], unsubscribe);
+        assert!(subscribe.is_empty());
+    }
+}